@@ -15,7 +15,14 @@ class DfEmbedder:
         write_buffer_size=2000,
         database_name="./lance_db",
         table_name="embeddings", # Default table name for convenience
-        vector_dim=1024,
+        vector_dim=None,
+        model_name="static-retrieval-mrl-en-v1",
+        backend="static",
+        weight_precision="f32",
+        query_cache_size=1024,
+        model_base_url=None,
+        consistency="latest",
+        consistency_interval=None,
     ):
         """
         Initialize the DfEmbedder, creating an instance of the Rust backend class.
@@ -27,7 +34,30 @@ class DfEmbedder:
             database_name: Path to the Lance database directory (default: "./lance_db").
             table_name: Default name for the Lance table within the database (default: "embeddings").
                       This can be overridden in the `index_table` method.
-            vector_dim: Dimensionality of the embedding vectors (default: 1024).
+            vector_dim: Dimensionality of the embedding vectors (default: None, the dimension
+                        of the model weights, e.g. 1024 for the default model). Smaller values
+                        (e.g. 64/128/256/512) truncate the Matryoshka embeddings.
+            model_name: Name of a registered static embedding model, or a path to a local model
+                        directory containing model.safetensors and tokenizer.json
+                        (default: "static-retrieval-mrl-en-v1"). With the "bert" backend, a path to
+                        a local sentence-transformers model directory.
+            backend: Embedding backend, "static" for static embedding models or "bert" for a local
+                     BERT/MiniLM sentence-transformers model run on CPU (default: "static").
+            weight_precision: In-memory precision of the static embedding table: "f32", "f16" or
+                              "int8" (int8 with a per-row scale). Lower precision reduces memory
+                              use; pooling still accumulates in f32 (default: "f32").
+            query_cache_size: Number of query embeddings kept in an LRU cache, so repeated
+                              `find_similar` queries are not re-embedded. 0 disables the cache
+                              (default: 1024).
+            model_base_url: Host to download registered models from, e.g. an internal mirror of
+                            the Hugging Face hub with the same layout (default: None, the
+                            DFEMBED_MODEL_BASE_URL environment variable or https://huggingface.co).
+            consistency: Searches reuse the opened handle of each table instead of reopening it.
+                         "latest" checks for newer versions of the table before every search,
+                         "interval" at most every `consistency_interval` seconds, and "pinned"
+                         keeps the version first opened until `refresh_table` (default: "latest").
+            consistency_interval: Seconds between version checks with the "interval"
+                                  consistency (default: None).
         """
         # Store Python-side config/defaults
         self.database_name = database_name
@@ -42,9 +72,31 @@ class DfEmbedder:
             database_name=database_name,
             # table_name=table_name, # Removed
             vector_dim=vector_dim,
+            model_name=model_name,
+            backend=backend,
+            weight_precision=weight_precision,
+            query_cache_size=query_cache_size,
+            model_base_url=model_base_url,
+            consistency=consistency,
+            consistency_interval=consistency_interval,
         )
 
-    def index_table(self, table: pa.Table, table_name: str | None = None):
+    def index_table(
+        self,
+        table: pa.Table,
+        table_name: str | None = None,
+        token_weighting="mean",
+        invalid_vector_policy="skip",
+        projection: str | None = None,
+        projection_dim: int | None = None,
+        quantization: str = "none",
+        keep_full_precision: bool = True,
+        full_text_columns: list[str] | None = None,
+        payload_columns: list[str] | None = None,
+        vector_index_threshold: int | None = None,
+        vector_index_type: str = "IVF_PQ",
+        metric: str | None = None,
+    ) -> dict[str, int]:
         """
         Index an Arrow table using the configured Rust backend.
 
@@ -52,9 +104,49 @@ class DfEmbedder:
             table: PyArrow Table object to index.
             table_name: Name of the table to create/update in the database.
                         If None, uses the default table name provided during initialization.
+            token_weighting: How token embeddings are pooled: "mean", "sif" (smooth inverse
+                             frequency) or "idf". SIF and IDF weights are fitted on the rows of
+                             the table, stored with it and reused for queries. Only supported by
+                             the static backend, and fixed when the table is created (default: "mean").
+            invalid_vector_policy: What to do with rows whose embedding is a zero vector (e.g. empty
+                                   text) or contains NaN values: "skip" leaves them out, "null"
+                                   stores them with a null vector and "fail" stops indexing with an
+                                   error. Batches written before the error stay in the table, which
+                                   is left partially written (default: "skip").
+            projection: Linear transform fitted on a sample of the rows when the table is created:
+                        "pca" centers the vectors and rotates them onto their principal axes,
+                        "whiten" also scales every axis to unit variance. The transform is stored
+                        with the table and applied to later appends and to queries (default: None).
+            projection_dim: Number of principal axes to keep with a projection, reducing the
+                            stored vector dimension (default: all of them).
+            quantization: Compact vector column written when the table is created: "int8" (one
+                          byte per dimension) or "binary" (one bit per dimension). Searches scan
+                          it for candidates and rescore them with the f32 vectors (default: "none").
+            keep_full_precision: Whether the f32 vector column is written next to the quantized
+                                 one. Without it the table is smaller, but searches cannot be
+                                 rescored or run exactly (default: True).
+            full_text_columns: Columns of the Lance table to build a full-text (BM25) index on
+                               once the rows are written, e.g. ["text"]. Needed by hybrid
+                               searches. Existing indices are updated on later appends
+                               (default: None).
+            payload_columns: Columns of the input table stored as they are next to the vectors,
+                             e.g. ["year", "category"], for filtering and returning them with
+                             the search results. Later appends must pass the same columns
+                             (default: None).
+            vector_index_threshold: Build a vector index once the table holds at least this
+                                    many rows, on this call or a later append, if it has none
+                                    yet (default: None, never built automatically).
+            vector_index_type: Type of the automatically built vector index, see
+                               create_vector_index (default: "IVF_PQ").
+            metric: Distance between the query and the stored vectors: "l2", "cosine" or "dot".
+                    Recorded with the table when it is created and used by its searches and
+                    vector indices; later appends keep the stored metric (default: None,
+                    "l2" for new tables).
 
         Returns:
-            None. Raises an exception on Rust error.
+            A dict with the number of "rows_indexed", the number of "zero_vectors" and
+            "non_finite_vectors" found, and how many of them were "skipped_rows" or stored as
+            "null_vectors". Raises an exception on Rust error.
         """
         if not isinstance(table, pa.Table):
             raise TypeError("Input must be a PyArrow Table object.")
@@ -65,7 +157,64 @@ class DfEmbedder:
              raise ValueError("Table name must be provided either during initialization or in the index_table call.")
 
         # Delegate the call to the Rust instance method, passing the table_name
-        self._rust_embedder.index_table(table, target_table_name)
+        return self._rust_embedder.index_table(
+            table,
+            target_table_name,
+            token_weighting,
+            invalid_vector_policy,
+            projection,
+            projection_dim,
+            quantization,
+            keep_full_precision,
+            full_text_columns,
+            payload_columns,
+            vector_index_threshold,
+            vector_index_type,
+            metric,
+        )
+
+    def embed_table(
+        self,
+        table: pa.Table,
+        table_name: str | None = None,
+        token_weighting: str = "mean",
+        projection: str | None = None,
+        projection_dim: int | None = None,
+        **serialization_options,
+    ) -> pa.Table:
+        """
+        Embed an Arrow table in memory, without writing it to the database.
+
+        Args:
+            table: PyArrow Table object to embed.
+            table_name: An indexed table whose stored token weights and projection are used,
+                        so that the vectors can be compared with its own. When None or when
+                        the table does not exist, token_weighting and projection are fitted on
+                        the input as index_table would for a new table (default: None).
+            token_weighting, projection, projection_dim: See index_table.
+            **serialization_options: How each row is serialized into the embedded text:
+                columns: Names of the columns to include, in order (default: all columns).
+                key_value_separator: Placed between a column name and its value (default: " is ").
+                field_separator: Placed between the columns of a row (default: "; ").
+
+        Returns:
+            A new PyArrow Table with the original columns, in the original row order, plus a
+            "vector" FixedSizeList<float32> column. Rows whose text produced a zero or NaN
+            embedding get a null vector.
+        """
+        if not isinstance(table, pa.Table):
+            raise TypeError("Input must be a PyArrow Table object.")
+
+        return pa.table(
+            self._rust_embedder.embed_table(
+                table,
+                table_name=table_name,
+                token_weighting=token_weighting,
+                projection=projection,
+                projection_dim=projection_dim,
+                **serialization_options,
+            )
+        )
 
     def analyze_table(self, table: pa.Table, detailed=False):
         """
@@ -86,7 +235,26 @@ class DfEmbedder:
         # The detailed parameter is currently ignored
         self._rust_embedder.analyze_table(table)
 
-    def find_similar(self, query: str, table_name: str, k: int):
+    def find_similar(
+        self,
+        query: str,
+        table_name: str,
+        k: int,
+        use_cache: bool = True,
+        rescore: bool = True,
+        mode: str = "vector",
+        fusion: str = "rrf",
+        vector_weight: float = 0.5,
+        where: str | None = None,
+        prefilter: bool = True,
+        output: str = "text",
+        columns: list[str] | None = None,
+        metric: str | None = None,
+        nprobes: int | None = None,
+        refine_factor: int | None = None,
+        ef: int | None = None,
+        use_index: bool = True,
+    ):
         """
         Find similar items in the database using the configured Rust backend.
 
@@ -94,15 +262,386 @@ class DfEmbedder:
             query: The query string to find similar items for.
             table_name: The name of the table to search in.
             k: The number of similar items to return.
+            use_cache: Whether to look up and store the query embedding in the query cache
+                       (default: True).
+            rescore: On quantized tables, whether the quantized candidates are reordered by
+                     their exact f32 distance (default: True).
+            mode: "vector" for a vector search, or "hybrid" to also run a BM25 search on the
+                  full-text indexed columns and fuse both results (default: "vector").
+            fusion: How hybrid results are fused: "rrf" (reciprocal rank fusion) or "weighted"
+                    (blend of the min-max normalized scores) (default: "rrf").
+            vector_weight: Weight of the vector scores in the "weighted" fusion, the BM25
+                           scores getting 1 - vector_weight (default: 0.5).
+            where: SQL predicate on the stored columns, e.g.
+                   "year > 2010 AND genre = 'Comedy'" (default: None).
+            prefilter: Whether the where clause is applied before the vector search, so that k
+                       matching rows are returned. With False the k nearest rows are filtered
+                       afterwards, which is faster but may return fewer than k rows. Quantized
+                       tables always prefilter (default: True).
+            output: "text" returns the texts, "records" a list of dicts and "arrow" a PyArrow
+                    Table, one row per result with its "_rowid", "text", "_distance" to the query
+                    (lower is closer) and the requested columns (default: "text").
+            columns: Stored columns returned with "records" and "arrow" results
+                     (default: all the payload columns).
+            metric: Distance used by this search instead of the metric of the table: "l2",
+                    "cosine" or "dot". A vector index built for another metric is not used
+                    (default: None, the metric of the table or of its vector index).
+            nprobes: Number of IVF partitions the vector index search visits. More partitions
+                     raise recall and latency (default: None, Lance's default).
+            refine_factor: Reranks refine_factor * k candidates of the vector index with the
+                           f32 vectors, raising recall at the cost of reading them
+                           (default: None, no reranking).
+            ef: Candidate list size of the HNSW search, only for IVF_HNSW_SQ indexes; must be
+                at least the number of rows the vector search looks for (k, 4 * k in hybrid
+                mode), larger values raise recall (default: None, Lance's default).
+            use_index: Whether the vector index is used. False runs an exact search over all
+                       the f32 vectors, and cannot be combined with nprobes, refine_factor or
+                       ef (default: True).
+
+        Returns:
+            The k most similar items to the query, closest first, in the requested output. In
+            hybrid mode, the "records" and "arrow" rows have the fused "score", and the
+            "vector_distance" and BM25 "text_score" of each retriever (None when the retriever
+            did not find the row) in place of "_distance".
+        """
+        result = self._rust_embedder.find_similar(
+            query,
+            table_name,
+            k,
+            use_cache,
+            rescore,
+            mode,
+            fusion,
+            vector_weight,
+            where,
+            prefilter,
+            output,
+            columns,
+            metric,
+            nprobes,
+            refine_factor,
+            ef,
+            use_index,
+        )
+        return self._rows_output(result, output)
+
+    def find_similar_batch(
+        self,
+        queries,
+        table_name: str,
+        k: int,
+        rescore: bool = True,
+        where: str | None = None,
+        prefilter: bool = True,
+        columns: list[str] | None = None,
+        metric: str | None = None,
+    ) -> pa.Table:
+        """
+        Run a vector search for each of many queries in one call. The queries are embedded
+        together in parallel, without the query cache, and searched concurrently.
+
+        Args:
+            queries: A list of query strings, or a PyArrow string array / chunked array.
+            table_name: The name of the table to search in.
+            k: The number of similar items to return per query.
+            rescore: See find_similar (default: True).
+            where: SQL predicate on the stored columns, applied to every query (default: None).
+            prefilter: See find_similar (default: True).
+            columns: Stored columns returned with the results (default: all the payload columns).
+            metric: See find_similar (default: None, the metric of the table or of its index).
+
+        Returns:
+            A PyArrow Table with the "query_idx" of each result, the position of its query in
+            `queries`, followed by its "_rowid", "text", "_distance" and the requested columns.
+            The results of each query are consecutive and closest first.
+        """
+        return pa.table(
+            self._rust_embedder.find_similar_batch(
+                queries,
+                table_name,
+                k,
+                rescore,
+                where,
+                prefilter,
+                columns,
+                metric,
+            )
+        )
+
+    def find_similar_by_vector(
+        self,
+        vector,
+        table_name: str,
+        k: int,
+        rescore: bool = True,
+        where: str | None = None,
+        prefilter: bool = True,
+        output: str = "text",
+        columns: list[str] | None = None,
+        metric: str | None = None,
+    ):
+        """
+        Find the items nearest to a vector instead of a query string, e.g. a vector produced by
+        another model or read from a table.
+
+        Args:
+            vector: A list or 1-d NumPy array of floats in the space of the stored vectors,
+                    with their dimension.
+            table_name: The name of the table to search in.
+            k: The number of similar items to return.
+            rescore, where, prefilter, output, columns, metric: See find_similar.
+
+        Returns:
+            The k nearest items to the vector, closest first, in the requested output.
+        """
+        result = self._rust_embedder.find_similar_by_vector(
+            vector,
+            table_name,
+            k,
+            rescore,
+            where,
+            prefilter,
+            output,
+            columns,
+            metric,
+        )
+        return self._rows_output(result, output)
+
+    def find_similar_to_row(
+        self,
+        row_id: int,
+        table_name: str,
+        k: int,
+        rescore: bool = True,
+        where: str | None = None,
+        prefilter: bool = True,
+        output: str = "text",
+        columns: list[str] | None = None,
+        metric: str | None = None,
+    ):
+        """
+        Find the items nearest to a stored row ("more like this"), using its stored vector as
+        the query. The row itself is left out of the results.
+
+        Args:
+            row_id: The "_rowid" of the row, as returned by the "records" and "arrow" outputs.
+            table_name: The name of the table to search in.
+            k: The number of similar items to return.
+            rescore, where, prefilter, output, columns, metric: See find_similar.
+
+        Returns:
+            The k nearest other items to the row, closest first, in the requested output.
+        """
+        result = self._rust_embedder.find_similar_to_row(
+            row_id,
+            table_name,
+            k,
+            rescore,
+            where,
+            prefilter,
+            output,
+            columns,
+            metric,
+        )
+        return self._rows_output(result, output)
+
+    @staticmethod
+    def _rows_output(result, output: str):
+        if output == "text":
+            return result
+        table = pa.table(result)
+        return table.to_pylist() if output == "records" else table
+
+    def create_vector_index(
+        self,
+        table_name: str,
+        type: str = "IVF_PQ",
+        metric: str | None = None,
+        num_partitions: int | None = None,
+        num_sub_vectors: int | None = None,
+        num_edges: int = 20,
+        ef_construction: int = 150,
+    ):
+        """
+        Build an approximate nearest neighbour index on the vector column of a table,
+        replacing an existing one. Later searches of the table use the index.
+
+        Args:
+            table_name: The name of the table to index.
+            type: "IVF_PQ" (product quantized partitions, compact and fast to build, needs at
+                  least 256 rows) or "IVF_HNSW_SQ" (an HNSW graph over scalar quantized vectors
+                  in each partition, higher recall at a larger size) (default: "IVF_PQ").
+            metric: Distance the index is built for and searches use: "l2", "cosine" or
+                    "dot" (default: None, the metric of the table).
+            num_partitions: Number of IVF partitions (default: square root of the row count).
+            num_sub_vectors: Number of PQ sub-vectors, must divide the vector dimension
+                             (default: dimension / 16, or dimension / 8).
+            num_edges: Neighbours per node of the HNSW graph (default: 20).
+            ef_construction: Candidate list size while building the HNSW graph (default: 150).
+
+        Returns:
+            None. Raises an exception on Rust error.
+        """
+        self._rust_embedder.create_vector_index(
+            table_name,
+            type,
+            metric,
+            num_partitions,
+            num_sub_vectors,
+            num_edges,
+            ef_construction,
+        )
+
+    def create_scalar_index(self, table_name: str, column: str, kind: str = "btree"):
+        """
+        Build an index on a payload column of a table, replacing an existing one, so that
+        filters on the column read the index instead of scanning it.
+
+        Args:
+            table_name: The name of the table.
+            column: The column to index, usually one of the payload columns. The text and vector
+                    columns cannot be indexed.
+            kind: "btree" for range and equality filters on columns with many distinct values
+                  (years, prices, ids), "bitmap" for equality filters on columns with few
+                  distinct values (categories, tenants), or "label_list" for list columns
+                  filtered with array_has_any / array_has_all (default: "btree").
+
+        Returns:
+            None. Raises an exception on Rust error.
+        """
+        self._rust_embedder.create_scalar_index(table_name, column, kind)
+
+    def storage_stats(self, table_name: str) -> dict[str, int]:
+        """
+        Get the size of the vector data of a table.
+
+        Args:
+            table_name: The name of the table.
+
+        Returns:
+            A dict with the number of "rows", the "vector_dim", the "full_precision_bytes_per_row"
+            and "quantized_bytes_per_row", the total "vector_bytes" and the "disk_bytes" taken by
+            all the table files.
+        """
+        return self._rust_embedder.storage_stats(table_name)
+
+    def quantization_recall(
+        self, table_name: str, queries: list[str], k: int = 10
+    ) -> dict[str, float]:
+        """
+        Measure how well the quantized search of a table finds the exact nearest rows.
+        The table must keep its full precision vectors.
+
+        Args:
+            table_name: The name of a quantized table.
+            queries: Sample queries to search for.
+            k: The number of nearest rows compared per query (default: 10).
+
+        Returns:
+            A dict with the "recall" at k of the quantized search and the "rescored_recall"
+            after rescoring its candidates, plus the number of "queries" and "k".
+        """
+        return self._rust_embedder.quantization_recall(table_name, queries, k)
+
+    def query_cache_stats(self) -> dict[str, int]:
+        """
+        Get the query cache counters.
+
+        Returns:
+            A dict with the number of cache "hits" and "misses", the current "size" and the
+            "capacity" of the cache.
+        """
+        return self._rust_embedder.query_cache_stats()
+
+    def clear_query_cache(self):
+        """
+        Empty the query cache and reset its counters.
+        """
+        self._rust_embedder.clear_query_cache()
+
+    def pin_table_version(self, table_name: str, version: int | None = None) -> int:
+        """
+        Pin the searches of a table to one of its versions, whatever the consistency, e.g. to
+        serve a stable snapshot while it is being appended to.
+
+        Args:
+            table_name: The name of the table.
+            version: The version to pin (default: None, the latest version).
+
+        Returns:
+            The pinned version. Raises an exception on Rust error.
+        """
+        return self._rust_embedder.pin_table_version(table_name, version)
+
+    def refresh_table(self, table_name: str):
+        """
+        Drop the cached handle of a table, pinned or not, so that the next search reads its
+        latest version.
+        """
+        self._rust_embedder.refresh_table(table_name)
+
+    def embed_batch(self, texts, output: str = "numpy"):
+        """
+        Embed many strings in one call, in parallel on the configured number of threads.
+
+        Args:
+            texts: A list of strings, or a PyArrow string array / chunked array.
+            output: "numpy" for a float32 NumPy array of shape (len(texts), vector_dim), or
+                    "arrow" for a PyArrow FixedSizeList<float32> array (default: "numpy").
 
         Returns:
-            A list of the k most similar items to the query.
+            The embeddings, in the order of the input texts.
         """
-        return self._rust_embedder.find_similar(query, table_name, k)
+        result = self._rust_embedder.embed_batch(texts, output)
+        if output == "arrow":
+            return pa.array(result)
+        return result
+
+    def fine_tune(
+        self,
+        output_dir: str,
+        pairs: list[tuple[str, str]] | None = None,
+        table: pa.Table | None = None,
+        columns: tuple[str, str] | None = None,
+        epochs: int = 1,
+        learning_rate: float = 1e-3,
+        batch_size: int = 64,
+        temperature: float = 0.05,
+    ) -> list[float]:
+        """
+        Adapt the static embedding table of the configured model to a domain vocabulary, on CPU,
+        with an in-batch contrastive loss, and save the result as a new local model.
+
+        Args:
+            output_dir: Directory the adapted model is saved to. Pass it as `model_name` to a new
+                        DfEmbedder to use the model.
+            pairs: (query, text) pairs that should embed close to each other.
+            table: Alternatively, a PyArrow Table to take self-supervised pairs from.
+            columns: The two columns of `table` to pair, e.g. ("title", "overview").
+            epochs: Number of passes over the pairs (default: 1).
+            learning_rate: AdamW learning rate (default: 1e-3).
+            batch_size: Pairs per step; the other pairs of a batch serve as negatives (default: 64).
+            temperature: Softmax temperature of the similarity scores (default: 0.05).
+
+        Returns:
+            The mean loss of each epoch.
+        """
+        if table is not None and not isinstance(table, pa.Table):
+            raise TypeError("table must be a PyArrow Table object.")
+        return self._rust_embedder.fine_tune(
+            output_dir,
+            pairs,
+            table,
+            columns,
+            epochs,
+            learning_rate,
+            batch_size,
+            temperature,
+        )
 
     def embed_string(self, text: str) -> list[float]:
         """
-        Embed a single string using the static embedding model.
+        Embed a single string using the configured embedding model.
 
         Args:
             text: The string to embed.
@@ -1,5 +1,6 @@
 from typing import List, Any, Optional
-import uuid
+import math
+import re
 
 # --- LlamaIndex Core Imports ---
 from llama_index.core.vector_stores.types import (
@@ -7,6 +8,8 @@ from llama_index.core.vector_stores.types import (
     VectorStoreQuery,
     VectorStoreQueryResult,
     ExactMatchFilter,
+    FilterOperator,
+    MetadataFilter,
     MetadataFilters,
 )
 from llama_index.core.schema import TextNode, NodeWithScore
@@ -18,6 +21,111 @@ from llama_index.core.embeddings import MockEmbedding # To satisfy LlamaIndex se
 
 from .core import DfEmbedder # Use relative import within the package
 
+# --- Metadata Filter Translation ---
+
+_COMPARISONS = {
+    FilterOperator.EQ: "=",
+    FilterOperator.NE: "!=",
+    FilterOperator.GT: ">",
+    FilterOperator.GTE: ">=",
+    FilterOperator.LT: "<",
+    FilterOperator.LTE: "<=",
+}
+
+
+def _sql_identifier(key: str) -> str:
+    """Quote a column name unless it is a plain identifier."""
+    if re.fullmatch(r"[A-Za-z_][A-Za-z0-9_]*", key):
+        return key
+    return "`" + key.replace("`", "``") + "`"
+
+
+def _sql_literal(value: Any) -> str:
+    """Render a filter value as a SQL literal."""
+    if value is None:
+        return "NULL"
+    if isinstance(value, bool):
+        return "TRUE" if value else "FALSE"
+    if isinstance(value, float) and not math.isfinite(value):
+        raise ValueError(f"Filter values must be finite numbers, got {value!r}")
+    if isinstance(value, (int, float)):
+        return repr(value)
+    if isinstance(value, str):
+        return "'" + value.replace("'", "''") + "'"
+    raise ValueError(f"Unsupported filter value: {value!r}")
+
+
+def _sql_list(values: Any) -> str:
+    if not isinstance(values, (list, tuple)) or not values:
+        raise ValueError(f"Expected a non-empty list of values, got {values!r}")
+    return ", ".join(_sql_literal(value) for value in values)
+
+
+def _filter_to_where(metadata_filter: MetadataFilter) -> str:
+    column = _sql_identifier(metadata_filter.key)
+    operator = metadata_filter.operator
+    value = metadata_filter.value
+    # comparisons with NULL never match, a missing value is tested with IS NULL
+    if value is None and operator == FilterOperator.EQ:
+        return f"{column} IS NULL"
+    if value is None and operator == FilterOperator.NE:
+        return f"{column} IS NOT NULL"
+    if operator in _COMPARISONS:
+        return f"{column} {_COMPARISONS[operator]} {_sql_literal(value)}"
+    if operator == FilterOperator.IN:
+        return f"{column} IN ({_sql_list(value)})"
+    if operator == FilterOperator.NIN:
+        return f"{column} NOT IN ({_sql_list(value)})"
+    if operator == FilterOperator.ANY:
+        return f"array_has_any({column}, [{_sql_list(value)}])"
+    if operator == FilterOperator.ALL:
+        return f"array_has_all({column}, [{_sql_list(value)}])"
+    if operator == FilterOperator.CONTAINS:
+        return f"array_has({column}, {_sql_literal(value)})"
+    if operator == FilterOperator.TEXT_MATCH:
+        escaped = str(value).replace("\\", "\\\\").replace("%", "\\%").replace("_", "\\_")
+        return f"{column} LIKE {_sql_literal('%' + escaped + '%')}"
+    if operator.value == "is_empty":
+        return f"{column} IS NULL"
+    raise ValueError(f"Unsupported metadata filter operator: {operator}")
+
+
+def metadata_filters_to_where(filters: MetadataFilters) -> Optional[str]:
+    """
+    Translate LlamaIndex MetadataFilters into a SQL where clause for DfEmbedder.find_similar.
+
+    Each filter key must name a stored column of the table (see the payload_columns
+    argument of DfEmbedder.index_table). Nested MetadataFilters are grouped in parentheses.
+
+    Returns:
+        The where clause, or None when there are no filters.
+    """
+    clauses = []
+    for metadata_filter in filters.filters:
+        if isinstance(metadata_filter, MetadataFilters):
+            clause = metadata_filters_to_where(metadata_filter)
+        else:
+            clause = _filter_to_where(metadata_filter)
+        if clause:
+            clauses.append(f"({clause})")
+    if not clauses:
+        return None
+
+    condition = getattr(filters.condition, "value", filters.condition) or "and"
+    if condition == "and":
+        return " AND ".join(clauses)
+    if condition == "or":
+        return " OR ".join(clauses)
+    if condition == "not":
+        return "NOT (" + " AND ".join(clauses) + ")"
+    raise ValueError(f"Unsupported metadata filter condition: {condition}")
+
+
+def _distance_to_similarity(distance: float) -> float:
+    """Map a search distance (lower is closer) to a similarity in (0, 1], higher is closer."""
+    return 1.0 / (1.0 + max(distance, 0.0))
+
+
 # --- Custom Vector Store Implementation ---
 
 class DfEmbedVectorStore(VectorStore):
@@ -100,58 +208,48 @@ class DfEmbedVectorStore(VectorStore):
         k = query.similarity_top_k
         print(f"DfEmbedVectorStore: Received query: '{query_text}', k={k}")
 
-        # --- Metadata Filtering (Optional - Adapt if DfEmbedder supports it) ---
-        # This example assumes find_similar doesn't support LlamaIndex filters.
-        # If your DfEmbedder *can* filter (e.g., via SQL WHERE clause in LanceDB),
-        # you would parse query.filters here and pass them to find_similar.
-        if query.filters:
-            print(f"Warning: Metadata filters received but not implemented in this DfEmbedVectorStore example: {query.filters}")
-            # Example parsing (if you were to implement it):
-            # custom_filters = self._parse_llama_filters(query.filters)
-            # similar_texts = self._embedder.find_similar(..., filters=custom_filters)
+        # --- Metadata Filtering ---
+        # LlamaIndex filters become a SQL where clause on the stored payload columns,
+        # applied before the vector search unless prefilter=False is passed to query()
+        where = metadata_filters_to_where(query.filters) if query.filters else None
+        prefilter = kwargs.get("prefilter", True)
 
         # Call DfEmbedder's find_similar
         try:
             # Note: DfEmbedder.find_similar expects k to be int. LlamaIndex might provide Optional[int].
             if k is None:
                  raise ValueError("similarity_top_k must be provided in the query.")
-            similar_texts: List[str] = self._embedder.find_similar(
+            records: List[dict] = self._embedder.find_similar(
                 query=query_text,
                 table_name=self._table_name,
-                k=k
+                k=k,
+                where=where,
+                prefilter=prefilter,
+                output="records",
             )
-            print(f"DfEmbedVectorStore: find_similar returned {len(similar_texts)} results.")
+            print(f"DfEmbedVectorStore: find_similar returned {len(records)} results.")
         except Exception as e:
             print(f"Error during embedder.find_similar: {e}")
             raise # Re-raise the exception
 
         # --- Result Conversion ---
         nodes: List[TextNode] = []
-        similarities: List[float] = [] # find_similar doesn't return scores, so we generate dummy ones
-        ids: List[str] = [] # Initialize list to store node IDs
+        similarities: List[float] = []
+        ids: List[str] = []
 
-        for i, text_content in enumerate(similar_texts):
-            # Create a unique ID for the node
-            node_id = f"{self._table_name}_result_{uuid.uuid4()}"
-            ids.append(node_id) # Add the generated ID to the list
-            # Assign a simple rank-based score (higher rank = higher score)
-            # You might get actual scores if find_similar returned them
-            score = 1.0 - (i / k) if k > 0 else 1.0 # Handle k=0 edge case
+        for record in records:
+            # The Lance row id identifies the row within the table
+            node_id = f"{self._table_name}_{record.pop('_rowid')}"
+            ids.append(node_id)
+            similarities.append(_distance_to_similarity(record.pop("_distance")))
 
             node = TextNode(
-                text=text_content,
+                text=record.pop("text"),
                 id_=node_id,
-                # You could add metadata here if find_similar returned it
-                # metadata={"source_table": self._table_name}
+                # The remaining fields are the stored payload columns
+                metadata=record,
             )
             nodes.append(node)
-            similarities.append(score)
 
         # Pass the collected IDs to the result object
         return VectorStoreQueryResult(nodes=nodes, similarities=similarities, ids=ids)
-
-    # Helper function if you implement filter parsing
-    # def _parse_llama_filters(self, standard_filters: MetadataFilters) -> Any:
-    #     # Implement logic to convert LlamaIndex filters to whatever
-    #     # DfEmbedder/LanceDB expects (e.g., SQL WHERE clause string)
-    #     raise NotImplementedError("Filter parsing not implemented.") 
\ No newline at end of file
@@ -1,8 +1,86 @@
-use arrow::datatypes::SchemaRef;
+use arrow::array::{Array, ArrayRef, AsArray, FixedSizeListArray, Float32Array};
+use arrow::buffer::NullBuffer;
+use arrow::datatypes::{DataType, Field, SchemaRef};
+use arrow::error::ArrowError;
 use pyo3::Bound;
 use pyo3::prelude::*;
 use pyo3::types::{PyAny, PyCapsule};
-use pyo3_arrow::PyTable;
+use pyo3_arrow::{PyArray, PyChunkedArray, PyTable};
+use std::sync::Arc;
+
+/// Strings to embed, passed from Python as a list or as an Arrow string array
+pub enum TextInput {
+    List(Vec<String>),
+    Arrow(Vec<ArrayRef>),
+}
+
+impl TextInput {
+    /// Extracts a list of str, or an Arrow string array or chunked array
+    pub fn extract(texts: &Bound<'_, PyAny>) -> PyResult<Self> {
+        if texts.hasattr("__arrow_c_array__")? {
+            let array: PyArray = texts.extract()?;
+            return Ok(TextInput::Arrow(vec![array.array().clone()]));
+        }
+        if texts.hasattr("__arrow_c_stream__")? {
+            let chunked: PyChunkedArray = texts.extract()?;
+            return Ok(TextInput::Arrow(chunked.chunks().to_vec()));
+        }
+        texts
+            .extract::<Vec<String>>()
+            .map(TextInput::List)
+            .map_err(|_| {
+                PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                    "texts must be a list of str or an Arrow string array",
+                )
+            })
+    }
+
+    /// Borrows the strings without copying them
+    pub fn as_strs(&self) -> Result<Vec<&str>, ArrowError> {
+        match self {
+            TextInput::List(texts) => Ok(texts.iter().map(|text| text.as_str()).collect()),
+            TextInput::Arrow(chunks) => {
+                let mut texts = Vec::new();
+                for chunk in chunks {
+                    if chunk.null_count() > 0 {
+                        return Err(ArrowError::InvalidArgumentError(
+                            "Cannot embed null strings".to_string(),
+                        ));
+                    }
+                    match chunk.data_type() {
+                        DataType::Utf8 => texts.extend(chunk.as_string::<i32>().iter().flatten()),
+                        DataType::LargeUtf8 => {
+                            texts.extend(chunk.as_string::<i64>().iter().flatten())
+                        }
+                        DataType::Utf8View => texts.extend(chunk.as_string_view().iter().flatten()),
+                        dt => {
+                            return Err(ArrowError::InvalidArgumentError(format!(
+                                "Expected a string array, got {}",
+                                dt
+                            )));
+                        }
+                    }
+                }
+                Ok(texts)
+            }
+        }
+    }
+}
+
+/// Wraps a row-major buffer of `dim`-sized vectors as a `FixedSizeList<Float32>` array,
+/// reusing the buffer instead of copying it
+pub fn vectors_to_fixed_size_list(
+    values: Vec<f32>,
+    dim: usize,
+    nulls: Option<NullBuffer>,
+) -> Result<FixedSizeListArray, ArrowError> {
+    FixedSizeListArray::try_new(
+        Arc::new(Field::new("item", DataType::Float32, true)),
+        dim as i32,
+        Arc::new(Float32Array::from(values)),
+        nulls,
+    )
+}
 
 /// Converts a PyArrow table to an Arrow table using the Arrow C Data Interface
 pub fn convert_py_to_arrow_table(py_arrow_table: &Bound<'_, PyAny>) -> PyResult<PyTable> {
@@ -0,0 +1,270 @@
+use anyhow::Result;
+use candle::{Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use std::fs;
+use std::path::Path;
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+use tracing::debug;
+
+use crate::embedding::embedder::Embedder;
+use crate::embedding::static_embeder::EmbedderError;
+
+const MODEL_FILES: [&str; 3] = ["config.json", "model.safetensors", "tokenizer.json"];
+// Lower bound of the divisors of the pooling, so that rows without tokens stay zero vectors
+const EPSILON: f32 = 1e-12;
+
+/// Embeds text with a local sentence-transformers BERT model (e.g. all-MiniLM-L6-v2),
+/// mean pooling the last hidden state over the attention mask.
+pub struct BertEmbedder {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    model_id: String,
+    pub embedding_dim: usize,
+    device: Device,
+}
+
+impl BertEmbedder {
+    /// Load a model from a directory containing config.json, model.safetensors and tokenizer.json
+    pub fn from_dir(model_dir: &Path) -> Result<Self> {
+        for file in MODEL_FILES.iter() {
+            if !model_dir.join(file).exists() {
+                return Err(EmbedderError::ModelLoadError(format!(
+                    "BERT model directory {} is missing {}",
+                    model_dir.display(),
+                    file
+                ))
+                .into());
+            }
+        }
+
+        let config: Config =
+            serde_json::from_str(&fs::read_to_string(model_dir.join("config.json"))?)?;
+
+        // Load tokenizer, padding to the longest sequence and truncating to the model limit
+        let mut tokenizer =
+            Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(|e| {
+                EmbedderError::ModelLoadError(format!("Failed to load tokenizer: {}", e))
+            })?;
+        let pp = PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..Default::default()
+        };
+        tokenizer.with_padding(Some(pp));
+        let tp = TruncationParams {
+            max_length: config.max_position_embeddings,
+            ..Default::default()
+        };
+        tokenizer
+            .with_truncation(Some(tp))
+            .map_err(|e| EmbedderError::ModelLoadError(format!("{}", e)))?;
+
+        let device = Device::Cpu;
+        let weights_path = model_dir.join("model.safetensors");
+        // SAFETY: the weights file is not modified while the model is loaded
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights_path], DTYPE, &device)? };
+        let model = BertModel::load(vb, &config)?;
+        debug!(
+            "Loaded BERT model from {} with hidden size {}",
+            model_dir.display(),
+            config.hidden_size
+        );
+
+        Ok(Self {
+            model,
+            tokenizer,
+            model_id: model_dir.to_string_lossy().to_string(),
+            embedding_dim: config.hidden_size,
+            device,
+        })
+    }
+
+    /// Embed a batch of strings and return as Tensor
+    fn embed_batch_tensor(&self, texts: &[&str]) -> Result<Tensor> {
+        let tokens = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| {
+                EmbedderError::TokenizationError(format!("Failed to tokenize input: {}", e))
+            })?;
+
+        let input_ids = tokens
+            .iter()
+            .map(|encoding| Tensor::new(encoding.get_ids(), &self.device))
+            .collect::<candle::Result<Vec<_>>>()?;
+        let attention_mask = tokens
+            .iter()
+            .map(|encoding| Tensor::new(encoding.get_attention_mask(), &self.device))
+            .collect::<candle::Result<Vec<_>>>()?;
+        let input_ids = Tensor::stack(&input_ids, 0)?;
+        let attention_mask = Tensor::stack(&attention_mask, 0)?;
+        let token_type_ids = input_ids.zeros_like()?;
+
+        let hidden = self
+            .model
+            .forward(&input_ids, &token_type_ids, Some(&attention_mask))?;
+
+        // Mean pool over the non-padding tokens
+        let mask = attention_mask.to_dtype(DTYPE)?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let pooled = summed.broadcast_div(&mask.sum(1)?.maximum(EPSILON)?)?;
+
+        // Normalize embeddings, leaving zero vectors to the invalid vector policy
+        let norm = pooled.sqr()?.sum_keepdim(1)?.sqrt()?.maximum(EPSILON)?;
+        Ok(pooled.broadcast_div(&norm)?)
+    }
+}
+
+impl Embedder for BertEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self.embed_batch_tensor(texts)?.to_vec2::<f32>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const WORDS: [&str; 7] = ["[PAD]", "[UNK]", "red", "apple", "blue", "sky", "sun"];
+    const HIDDEN: usize = 8;
+    const INTERMEDIATE: usize = 16;
+    const MAX_POSITIONS: usize = 16;
+
+    // Write a local one layer BERT model with a word level tokenizer over WORDS and spread
+    // out weights, its layer norms keeping the identity
+    fn write_model(dir: &Path) -> Result<()> {
+        let config = serde_json::json!({
+            "vocab_size": WORDS.len(),
+            "hidden_size": HIDDEN,
+            "num_hidden_layers": 1,
+            "num_attention_heads": 2,
+            "intermediate_size": INTERMEDIATE,
+            "hidden_act": "gelu",
+            "hidden_dropout_prob": 0.0,
+            "max_position_embeddings": MAX_POSITIONS,
+            "type_vocab_size": 2,
+            "initializer_range": 0.02,
+            "layer_norm_eps": 1e-12,
+            "pad_token_id": 0,
+            "classifier_dropout": null,
+            "model_type": "bert",
+        });
+        fs::write(dir.join("config.json"), config.to_string())?;
+
+        let vocab: serde_json::Map<String, serde_json::Value> = WORDS
+            .iter()
+            .enumerate()
+            .map(|(id, word)| (word.to_string(), id.into()))
+            .collect();
+        let tokenizer = serde_json::json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": {"type": "Whitespace"},
+            "post_processor": null,
+            "decoder": null,
+            "model": {"type": "WordLevel", "vocab": vocab, "unk_token": "[UNK]"},
+        });
+        fs::write(dir.join("tokenizer.json"), tokenizer.to_string())?;
+
+        let mut tensors = HashMap::new();
+        let mut spread = |name: &str, shape: &[usize]| -> Result<()> {
+            let len = shape.iter().product();
+            let values: Vec<f32> = (0..len)
+                .map(|i| ((i * 7919 + tensors.len() * 31) % 17) as f32 / 16.0 - 0.5)
+                .collect();
+            let tensor = Tensor::from_vec(values, shape, &Device::Cpu)?;
+            tensors.insert(name.to_string(), tensor);
+            Ok(())
+        };
+        spread("embeddings.word_embeddings.weight", &[WORDS.len(), HIDDEN])?;
+        spread(
+            "embeddings.position_embeddings.weight",
+            &[MAX_POSITIONS, HIDDEN],
+        )?;
+        spread("embeddings.token_type_embeddings.weight", &[2, HIDDEN])?;
+        let layer = "encoder.layer.0";
+        for name in ["query", "key", "value"] {
+            spread(
+                &format!("{layer}.attention.self.{name}.weight"),
+                &[HIDDEN, HIDDEN],
+            )?;
+            spread(&format!("{layer}.attention.self.{name}.bias"), &[HIDDEN])?;
+        }
+        spread(
+            &format!("{layer}.attention.output.dense.weight"),
+            &[HIDDEN, HIDDEN],
+        )?;
+        spread(&format!("{layer}.attention.output.dense.bias"), &[HIDDEN])?;
+        spread(
+            &format!("{layer}.intermediate.dense.weight"),
+            &[INTERMEDIATE, HIDDEN],
+        )?;
+        spread(&format!("{layer}.intermediate.dense.bias"), &[INTERMEDIATE])?;
+        spread(
+            &format!("{layer}.output.dense.weight"),
+            &[HIDDEN, INTERMEDIATE],
+        )?;
+        spread(&format!("{layer}.output.dense.bias"), &[HIDDEN])?;
+        for prefix in [
+            "embeddings.LayerNorm".to_string(),
+            format!("{layer}.attention.output.LayerNorm"),
+            format!("{layer}.output.LayerNorm"),
+        ] {
+            let ones = Tensor::ones(HIDDEN, DTYPE, &Device::Cpu)?;
+            tensors.insert(format!("{prefix}.weight"), ones);
+            let zeros = Tensor::zeros(HIDDEN, DTYPE, &Device::Cpu)?;
+            tensors.insert(format!("{prefix}.bias"), zeros);
+        }
+        candle::safetensors::save(&tensors, dir.join("model.safetensors"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bert_embeddings() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_model(dir.path())?;
+        let embedder = BertEmbedder::from_dir(dir.path())?;
+        assert_eq!(embedder.embedding_dim(), HIDDEN);
+
+        let embeddings = embedder.embed_batch_vec(&["red apple", "blue sky sun", "sky"])?;
+        assert_eq!(embeddings.len(), 3);
+        for embedding in &embeddings {
+            assert_eq!(embedding.len(), HIDDEN);
+            let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-4, "norm {}", norm);
+        }
+        // padding does not change the embedding of the shorter texts
+        let alone = embedder.embed_batch_vec(&["sky"])?;
+        for (a, b) in alone[0].iter().zip(&embeddings[2]) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bert_empty_text_is_zero_vector() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_model(dir.path())?;
+        let embedder = BertEmbedder::from_dir(dir.path())?;
+        // the empty text is all padding next to the other one
+        let embeddings = embedder.embed_batch_vec(&["red apple", ""])?;
+        assert!(embeddings[0].iter().all(|x| x.is_finite()));
+        assert!(embeddings[1].iter().all(|&x| x == 0.0));
+        Ok(())
+    }
+}
@@ -1,22 +1,106 @@
-use crate::{embedding::static_embeder::Embedder, storage::lance_writer::EmbeddingBatch};
+use crate::embedding::token_weights::TokenWeights;
+use crate::{embedding::embedder::Embedder, storage::lance_writer::EmbeddingBatch};
+use arrow::array::BooleanArray;
+use arrow::compute::filter_record_batch;
+use arrow::record_batch::RecordBatch;
 use crossbeam::channel::{Receiver, Sender};
 use rayon::ThreadPool;
+use rayon::prelude::*;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tracing::{debug, error, info};
 
+/// What to do with rows whose embedding has a zero norm or non-finite values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidVectorPolicy {
+    /// Leave the row out of the table
+    #[default]
+    Skip,
+    /// Store the row with a null vector
+    Null,
+    /// Stop indexing and return an error. Batches committed before the invalid row was
+    /// found stay in the table.
+    Fail,
+}
+
+impl FromStr for InvalidVectorPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "skip" => Ok(InvalidVectorPolicy::Skip),
+            "null" => Ok(InvalidVectorPolicy::Null),
+            "fail" => Ok(InvalidVectorPolicy::Fail),
+            other => Err(anyhow::anyhow!(
+                "Unknown invalid vector policy '{}': expected 'skip', 'null' or 'fail'",
+                other
+            )),
+        }
+    }
+}
+
+/// Number of invalid vectors found by the embedding workers
+#[derive(Debug, Default)]
+pub struct InvalidVectorCounts {
+    zero_norm: AtomicUsize,
+    non_finite: AtomicUsize,
+}
+
+impl InvalidVectorCounts {
+    pub fn zero_norm(&self) -> usize {
+        self.zero_norm.load(Ordering::Relaxed)
+    }
+
+    pub fn non_finite(&self) -> usize {
+        self.non_finite.load(Ordering::Relaxed)
+    }
+
+    pub fn total(&self) -> usize {
+        self.zero_norm() + self.non_finite()
+    }
+
+    /// Returns whether the vector can be stored, counting it otherwise
+    pub fn check(&self, vector: &[f32]) -> bool {
+        if vector.iter().any(|x| !x.is_finite()) {
+            self.non_finite.fetch_add(1, Ordering::Relaxed);
+            false
+        } else if vector.iter().all(|x| *x == 0.0) {
+            self.zero_norm.fetch_add(1, Ordering::Relaxed);
+            false
+        } else {
+            true
+        }
+    }
+}
+
+/// Serialized rows sent to the embedding workers, with the payload columns to store next to
+/// their vectors
+#[derive(Debug)]
+pub struct TextBatch {
+    pub texts: Vec<String>,
+    /// One row per text, `None` when no payload columns are stored
+    pub payload: Option<RecordBatch>,
+}
+
 pub struct EmbeddingCoordinator {
     thread_pool: ThreadPool,
     num_workers: usize,
-    reciever_channel: Receiver<Vec<String>>,
+    reciever_channel: Receiver<TextBatch>,
     sender_channel: Sender<EmbeddingBatch>,
     embedding_chunk_size: usize,
+    embedder: Arc<dyn Embedder>,
+    invalid_vector_policy: InvalidVectorPolicy,
+    invalid_vectors: Arc<InvalidVectorCounts>,
 }
 
 impl EmbeddingCoordinator {
     pub fn new(
         num_workers: usize,
-        reciever_channel: Receiver<Vec<String>>,
+        reciever_channel: Receiver<TextBatch>,
         sender_channel: Sender<EmbeddingBatch>,
         embedding_chunk_size: usize,
+        embedder: Arc<dyn Embedder>,
     ) -> Self {
         let threadpool = rayon::ThreadPoolBuilder::new().build().unwrap();
         Self {
@@ -25,25 +109,41 @@ impl EmbeddingCoordinator {
             reciever_channel,
             sender_channel,
             embedding_chunk_size,
+            embedder,
+            invalid_vector_policy: InvalidVectorPolicy::default(),
+            invalid_vectors: Arc::new(InvalidVectorCounts::default()),
         }
     }
 
+    pub fn with_invalid_vector_policy(mut self, policy: InvalidVectorPolicy) -> Self {
+        self.invalid_vector_policy = policy;
+        self
+    }
+
+    /// Counts of the invalid vectors found by the workers, updated as they run
+    pub fn invalid_vectors(&self) -> Arc<InvalidVectorCounts> {
+        Arc::clone(&self.invalid_vectors)
+    }
+
     pub fn start(self) {
         for _ in 0..self.num_workers {
             let receive_from_embedder = self.reciever_channel.clone();
             let send_to_writer_clone = self.sender_channel.clone();
             let chunk_size = self.embedding_chunk_size;
+            let embedder = Arc::clone(&self.embedder);
+            let policy = self.invalid_vector_policy;
+            let invalid_vectors = Arc::clone(&self.invalid_vectors);
 
             self.thread_pool.spawn(move || {
                 let thread_id = std::thread::current().id();
                 debug!("Starting embedding thread id {:?}", thread_id);
-                let embed_model_clone = Embedder::new().unwrap();
-                info!("Created embedder for thread id {:?}", thread_id);
                 embed_text_chunks(
                     receive_from_embedder,
                     send_to_writer_clone,
                     chunk_size,
-                    &embed_model_clone,
+                    embedder.as_ref(),
+                    policy,
+                    &invalid_vectors,
                 );
                 info!(
                     "Embedding thread id {:?} finished .. closing channel",
@@ -57,35 +157,59 @@ impl EmbeddingCoordinator {
 
 /// this method will continously receive records from the embedder, embed and then send the embeddings to the writer
 fn embed_text_chunks(
-    receive_from_embedder: Receiver<Vec<String>>,
+    receive_from_embedder: Receiver<TextBatch>,
     send_to_writer: Sender<EmbeddingBatch>,
     embedding_chunk_size: usize,
-    model: &Embedder,
+    model: &dyn Embedder,
+    policy: InvalidVectorPolicy,
+    invalid_vectors: &InvalidVectorCounts,
 ) {
     while let Ok(records) = receive_from_embedder.recv() {
-        records
-            .chunks(embedding_chunk_size)
-            .for_each(|chunk| match embed_chunk(chunk, model) {
+        for (chunk_idx, chunk) in records.texts.chunks(embedding_chunk_size).enumerate() {
+            // once a worker found an invalid vector, stop feeding the writer
+            if policy == InvalidVectorPolicy::Fail && invalid_vectors.total() > 0 {
+                break;
+            }
+            let payload = records
+                .payload
+                .as_ref()
+                .map(|payload| payload.slice(chunk_idx * embedding_chunk_size, chunk.len()));
+            match embed_chunk(chunk, model) {
                 Err(e) => {
                     error!("Error embedding chunk: {}", e);
                 }
                 Ok(embeddings) => {
-                    let embedding_batch = EmbeddingBatch {
-                        texts: chunk.to_vec(),
+                    let batch = apply_invalid_vector_policy(
+                        chunk,
+                        payload,
                         embeddings,
+                        policy,
+                        invalid_vectors,
+                    );
+                    let embedding_batch = match batch {
+                        Ok(Some(embedding_batch)) => embedding_batch,
+                        Ok(None) => {
+                            error!("Chunk produced invalid vectors, stopping");
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Error filtering the payload of a chunk: {}", e);
+                            continue;
+                        }
                     };
                     if let Err(e) = send_to_writer.send(embedding_batch) {
                         error!("Error sending batch to writer: {}", e);
                     }
                 }
-            });
+            }
+        }
     }
     info!("Embedding thread finished.. closing channel");
     drop(send_to_writer);
 }
 
 /// process the lines in batches and return the embeddings
-fn embed_chunk(chunk: &[String], model: &Embedder) -> anyhow::Result<Vec<Vec<f32>>> {
+fn embed_chunk(chunk: &[String], model: &dyn Embedder) -> anyhow::Result<Vec<Vec<f32>>> {
     let chunk_as_str: Vec<&str> = chunk.iter().map(|s| s.as_str()).collect();
     let embeddings = model.embed_batch(&chunk_as_str).unwrap();
     // convert this to a vec<vec<f32>>
@@ -96,3 +220,174 @@ fn embed_chunk(chunk: &[String], model: &Embedder) -> anyhow::Result<Vec<Vec<f32
 
     Ok(embeddings_vec)
 }
+
+/// Embed the texts on the threads of `thread_pool`, `chunk_size` texts at a time.
+/// Returns one row-major buffer of `texts.len() * embedding_dim` floats, in the input order.
+pub fn embed_in_parallel(
+    embedder: &dyn Embedder,
+    texts: &[&str],
+    chunk_size: usize,
+    thread_pool: &ThreadPool,
+) -> anyhow::Result<Vec<f32>> {
+    embed_in_parallel_weighted(embedder, texts, None, chunk_size, thread_pool)
+}
+
+/// Like `embed_in_parallel`, pooling the tokens with the given weights, e.g. the ones fitted
+/// on a table when embedding queries against it
+pub fn embed_in_parallel_weighted(
+    embedder: &dyn Embedder,
+    texts: &[&str],
+    weights: Option<&TokenWeights>,
+    chunk_size: usize,
+    thread_pool: &ThreadPool,
+) -> anyhow::Result<Vec<f32>> {
+    let dim = embedder.embedding_dim();
+    let chunk_size = chunk_size.max(1);
+    let mut embeddings = vec![0f32; texts.len() * dim];
+    if embeddings.is_empty() {
+        return Ok(embeddings);
+    }
+
+    // every chunk is embedded straight into its rows of the output buffer
+    thread_pool.install(|| {
+        texts
+            .par_chunks(chunk_size)
+            .zip(embeddings.par_chunks_mut(chunk_size * dim))
+            .try_for_each(|(chunk, rows)| embedder.embed_batch_into(chunk, weights, rows))
+    })?;
+    Ok(embeddings)
+}
+
+/// Pair the texts and payload rows with their embeddings, handling zero and non-finite
+/// vectors according to the policy. Returns `None` if the chunk must not be written.
+fn apply_invalid_vector_policy(
+    chunk: &[String],
+    payload: Option<RecordBatch>,
+    embeddings: Vec<Vec<f32>>,
+    policy: InvalidVectorPolicy,
+    invalid_vectors: &InvalidVectorCounts,
+) -> anyhow::Result<Option<EmbeddingBatch>> {
+    let mut batch = EmbeddingBatch {
+        texts: Vec::with_capacity(chunk.len()),
+        embeddings: Vec::with_capacity(chunk.len()),
+        payload: None,
+    };
+    let mut kept = Vec::with_capacity(chunk.len());
+    for (text, embedding) in chunk.iter().zip(embeddings) {
+        if invalid_vectors.check(&embedding) {
+            batch.texts.push(text.clone());
+            batch.embeddings.push(Some(embedding));
+            kept.push(true);
+            continue;
+        }
+        match policy {
+            InvalidVectorPolicy::Skip => kept.push(false),
+            InvalidVectorPolicy::Null => {
+                batch.texts.push(text.clone());
+                batch.embeddings.push(None);
+                kept.push(true);
+            }
+            InvalidVectorPolicy::Fail => return Ok(None),
+        }
+    }
+    batch.payload = match payload {
+        Some(payload) if kept.contains(&false) => {
+            Some(filter_record_batch(&payload, &BooleanArray::from(kept))?)
+        }
+        payload => payload,
+    };
+    Ok(Some(batch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Embeds a text as its length and its first byte
+    struct LengthEmbedder;
+
+    impl Embedder for LengthEmbedder {
+        fn model_id(&self) -> &str {
+            "length"
+        }
+
+        fn embedding_dim(&self) -> usize {
+            2
+        }
+
+        fn embed_batch_vec(&self, texts: &[&str]) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| vec![text.len() as f32, text.as_bytes()[0] as f32])
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_embed_in_parallel_keeps_order() -> anyhow::Result<()> {
+        let texts: Vec<String> = (0..100).map(|i| "x".repeat(i + 1)).collect();
+        let texts: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
+        let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(4).build()?;
+        let embeddings = embed_in_parallel(&LengthEmbedder, &texts, 7, &thread_pool)?;
+        assert_eq!(embeddings.len(), 200);
+        for (i, row) in embeddings.chunks_exact(2).enumerate() {
+            assert_eq!(row, [(i + 1) as f32, b'x' as f32]);
+        }
+        assert!(embed_in_parallel(&LengthEmbedder, &[], 7, &thread_pool)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_vector_policy() -> anyhow::Result<()> {
+        let chunk: Vec<String> = ["ok", "empty", "nan"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let embeddings = || vec![vec![0.6, 0.8], vec![0.0, 0.0], vec![f32::NAN, 0.0]];
+        let payload = RecordBatch::try_from_iter([(
+            "year",
+            Arc::new(arrow::array::Int32Array::from(vec![2001, 2002, 2003])) as _,
+        )])?;
+
+        let counts = InvalidVectorCounts::default();
+        let batch = apply_invalid_vector_policy(
+            &chunk,
+            Some(payload.clone()),
+            embeddings(),
+            InvalidVectorPolicy::Skip,
+            &counts,
+        )?
+        .unwrap();
+        assert_eq!(batch.texts, vec!["ok"]);
+        assert_eq!(batch.payload.map(|payload| payload.num_rows()), Some(1));
+        assert_eq!((counts.zero_norm(), counts.non_finite()), (1, 1));
+
+        let counts = InvalidVectorCounts::default();
+        let batch = apply_invalid_vector_policy(
+            &chunk,
+            Some(payload),
+            embeddings(),
+            InvalidVectorPolicy::Null,
+            &counts,
+        )?
+        .unwrap();
+        assert_eq!(batch.texts, chunk);
+        assert_eq!(batch.embeddings[1], None);
+        assert_eq!(batch.embeddings[2], None);
+        assert_eq!(batch.payload.map(|payload| payload.num_rows()), Some(3));
+
+        let counts = InvalidVectorCounts::default();
+        assert!(
+            apply_invalid_vector_policy(
+                &chunk,
+                None,
+                embeddings(),
+                InvalidVectorPolicy::Fail,
+                &counts
+            )?
+            .is_none()
+        );
+        assert_eq!(counts.total(), 1);
+        Ok(())
+    }
+}
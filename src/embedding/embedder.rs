@@ -0,0 +1,174 @@
+use anyhow::Result;
+use ndarray::Array2;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::embedding::bert_embeder::BertEmbedder;
+use crate::embedding::static_embeder::{EmbedderError, ModelSpec, StaticEmbedder, WeightPrecision};
+use crate::embedding::token_weights::{TokenWeighting, TokenWeights};
+
+/// A text embedding backend used for indexing and querying
+pub trait Embedder: Send + Sync {
+    /// Identifier of the loaded model, recorded in the table metadata
+    fn model_id(&self) -> &str;
+
+    /// Dimension of the returned embeddings
+    fn embedding_dim(&self) -> usize;
+
+    /// Embed a batch of strings and return L2-normalized vectors as Vec<Vec<f32>>
+    fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+
+    /// Fit per-token pooling weights on the rows of a table
+    fn fit_token_weights(
+        &self,
+        _texts: &[&str],
+        _weighting: TokenWeighting,
+    ) -> Result<TokenWeights> {
+        Err(anyhow::anyhow!(
+            "Token weighting is not supported for model {}",
+            self.model_id()
+        ))
+    }
+
+    /// Embed a batch of strings, pooling tokens with the given weights
+    fn embed_batch_vec_weighted(
+        &self,
+        _texts: &[&str],
+        _weights: &TokenWeights,
+    ) -> Result<Vec<Vec<f32>>> {
+        Err(anyhow::anyhow!(
+            "Token weighting is not supported for model {}",
+            self.model_id()
+        ))
+    }
+
+    /// Embed a batch of strings into `embeddings`, `embedding_dim` floats per string in the
+    /// input order, pooling tokens with `weights` when given
+    fn embed_batch_into(
+        &self,
+        texts: &[&str],
+        weights: Option<&TokenWeights>,
+        embeddings: &mut [f32],
+    ) -> Result<()> {
+        let dim = self.embedding_dim();
+        let vectors = match weights {
+            Some(weights) => self.embed_batch_vec_weighted(texts, weights)?,
+            None => self.embed_batch_vec(texts)?,
+        };
+        if vectors.len() != texts.len()
+            || embeddings.len() != texts.len() * dim
+            || vectors.iter().any(|vector| vector.len() != dim)
+        {
+            return Err(anyhow::anyhow!(
+                "Embedder returned unexpected vectors for a chunk of {} texts",
+                texts.len()
+            ));
+        }
+        for (vector, row) in vectors.iter().zip(embeddings.chunks_exact_mut(dim)) {
+            row.copy_from_slice(vector);
+        }
+        Ok(())
+    }
+
+    /// Embed a batch of strings and return as ndarray
+    fn embed_batch(&self, texts: &[&str]) -> Result<Array2<f32>> {
+        let flat_data: Vec<f32> = self.embed_batch_vec(texts)?.into_iter().flatten().collect();
+        Ok(Array2::from_shape_vec(
+            (texts.len(), self.embedding_dim()),
+            flat_data,
+        )?)
+    }
+}
+
+/// The available embedding backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Static embedding model (token embedding lookup and mean pooling)
+    Static,
+    /// Local sentence-transformers BERT/MiniLM model run through candle
+    Bert,
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "static" => Ok(Backend::Static),
+            "bert" => Ok(Backend::Bert),
+            other => Err(anyhow::anyhow!(
+                "Unknown embedding backend '{}': expected 'static' or 'bert'",
+                other
+            )),
+        }
+    }
+}
+
+/// Describes which embedding backend and model to load
+#[derive(Debug, Clone)]
+pub struct EmbedderConfig {
+    pub backend: Backend,
+    /// Model name or path to a local model directory
+    pub model: String,
+    /// Output dimension, defaults to the model dimension
+    pub output_dim: Option<usize>,
+    /// In-memory precision of the static embedding table
+    pub precision: WeightPrecision,
+    /// Host to download registered models from, defaults to the Hugging Face hub
+    pub base_url: Option<String>,
+}
+
+impl EmbedderConfig {
+    /// Load the embedder once; the result is shared by all worker threads
+    pub fn load(&self) -> Result<Arc<dyn Embedder>> {
+        match self.backend {
+            Backend::Static => {
+                let mut spec = ModelSpec::resolve(&self.model)?;
+                if let Some(base_url) = &self.base_url {
+                    spec = spec.with_base_url(base_url);
+                }
+                let embedder = StaticEmbedder::from_spec(&spec)?;
+                let dim = self.output_dim.unwrap_or(embedder.model_dim);
+                Ok(Arc::new(
+                    embedder
+                        .with_output_dim(dim)?
+                        .with_precision(self.precision),
+                ))
+            }
+            Backend::Bert => {
+                if self.precision != WeightPrecision::F32 {
+                    return Err(EmbedderError::ModelLoadError(
+                        "Reduced weight precision is only supported by the static backend"
+                            .to_string(),
+                    )
+                    .into());
+                }
+                let embedder = BertEmbedder::from_dir(Path::new(&self.model))?;
+                // BERT models are not Matryoshka-trained, so truncation is not supported
+                match self.output_dim {
+                    Some(dim) if dim != embedder.embedding_dim => {
+                        Err(EmbedderError::ModelLoadError(format!(
+                            "Invalid output dimension {}: BERT model {} has {} dimensions",
+                            dim, self.model, embedder.embedding_dim
+                        ))
+                        .into())
+                    }
+                    _ => Ok(Arc::new(embedder)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_str() {
+        assert_eq!("static".parse::<Backend>().unwrap(), Backend::Static);
+        assert_eq!("bert".parse::<Backend>().unwrap(), Backend::Bert);
+        assert!("onnx".parse::<Backend>().is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use anyhow::Result;
+use arrow::array::{Array, RecordBatch};
+use arrow::datatypes::Schema;
+use candle::{Device, Tensor, Var};
+use candle_nn::{AdamW, Optimizer, ParamsAdamW};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tokenizers::Tokenizer;
+use tracing::info;
+
+use crate::embedding::static_embeder::{
+    EmbedderError, ModelFormat, ModelSpec, load_weights_tensor,
+};
+use crate::indexer::extract_value_from_array;
+
+const WEIGHTS_FILE: &str = "model.safetensors";
+const TOKENIZER_FILE: &str = "tokenizer.json";
+
+/// Hyperparameters of the contrastive fine-tuning
+#[derive(Debug, Clone, PartialEq)]
+pub struct FineTuneConfig {
+    pub epochs: usize,
+    pub learning_rate: f64,
+    /// Pairs per step; the other positives of a batch serve as negatives
+    pub batch_size: usize,
+    /// Softmax temperature of the similarity logits
+    pub temperature: f64,
+    /// Seed of the pair shuffling between epochs
+    pub seed: u64,
+}
+
+impl Default for FineTuneConfig {
+    fn default() -> Self {
+        Self {
+            epochs: 1,
+            learning_rate: 1e-3,
+            batch_size: 64,
+            temperature: 0.05,
+            seed: 42,
+        }
+    }
+}
+
+/// Adapts the embedding table of a static model to (anchor, positive) text pairs, such as
+/// (query, row) pairs or two columns of the same row, with an in-batch contrastive loss.
+pub struct FineTuner {
+    weights: Var,
+    format: ModelFormat,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl FineTuner {
+    pub fn from_spec(spec: &ModelSpec) -> Result<Self> {
+        let model_dir = spec.ensure_model_files()?;
+        let (weights, format) = load_weights_tensor(&model_dir.join(WEIGHTS_FILE))?;
+        let mut tokenizer = Tokenizer::from_file(model_dir.join(TOKENIZER_FILE)).map_err(|e| {
+            EmbedderError::ModelLoadError(format!("Failed to load tokenizer: {}", e))
+        })?;
+        // tokenize the same way as the StaticEmbedder
+        tokenizer.with_padding(None);
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| EmbedderError::ModelLoadError(format!("{}", e)))?;
+
+        Ok(Self {
+            weights: Var::from_tensor(&weights)?,
+            format,
+            tokenizer,
+            device: Device::Cpu,
+        })
+    }
+
+    /// Train on the pairs and return the mean loss of each epoch
+    pub fn train(&self, pairs: &[(String, String)], config: &FineTuneConfig) -> Result<Vec<f32>> {
+        if pairs.len() < 2 {
+            return Err(anyhow::anyhow!(
+                "Fine-tuning needs at least 2 pairs, got {}",
+                pairs.len()
+            ));
+        }
+        let params = ParamsAdamW {
+            lr: config.learning_rate,
+            weight_decay: 0.0,
+            ..Default::default()
+        };
+        let mut optimizer = AdamW::new(vec![self.weights.clone()], params)?;
+
+        let mut order: Vec<usize> = (0..pairs.len()).collect();
+        let mut rng = config.seed.max(1);
+        let mut epoch_losses = Vec::with_capacity(config.epochs);
+        for epoch in 0..config.epochs {
+            shuffle(&mut order, &mut rng);
+            let (mut total_loss, mut num_steps) = (0f32, 0usize);
+            for batch in order.chunks(config.batch_size.max(2)) {
+                // a batch of one pair has no negatives
+                if batch.len() < 2 {
+                    continue;
+                }
+                let anchors: Vec<&str> = batch.iter().map(|&i| pairs[i].0.as_str()).collect();
+                let positives: Vec<&str> = batch.iter().map(|&i| pairs[i].1.as_str()).collect();
+                let loss = self.contrastive_loss(&anchors, &positives, config.temperature)?;
+                optimizer.backward_step(&loss)?;
+                total_loss += loss.to_scalar::<f32>()?;
+                num_steps += 1;
+            }
+            let epoch_loss = total_loss / num_steps.max(1) as f32;
+            info!("Fine-tuning epoch {} loss {:.4}", epoch + 1, epoch_loss);
+            epoch_losses.push(epoch_loss);
+        }
+        Ok(epoch_losses)
+    }
+
+    /// Symmetric InfoNCE loss of the pooled anchor and positive embeddings
+    fn contrastive_loss(
+        &self,
+        anchors: &[&str],
+        positives: &[&str],
+        temperature: f64,
+    ) -> Result<Tensor> {
+        let anchors = self.pool(anchors)?;
+        let positives = self.pool(positives)?;
+        let logits = anchors
+            .matmul(&positives.t()?)?
+            .affine(1.0 / temperature, 0.0)?;
+        let targets = Tensor::arange(0u32, logits.dim(0)? as u32, &self.device)?;
+        let forward = candle_nn::loss::cross_entropy(&logits, &targets)?;
+        let backward = candle_nn::loss::cross_entropy(&logits.t()?.contiguous()?, &targets)?;
+        Ok((forward + backward)?.affine(0.5, 0.0)?)
+    }
+
+    /// Mean pool and L2 normalize the token embeddings of each text, keeping the gradient
+    fn pool(&self, texts: &[&str]) -> Result<Tensor> {
+        let encodings = self
+            .tokenizer
+            .encode_batch_fast(texts.to_vec(), self.format.add_special_tokens())
+            .map_err(|e| {
+                EmbedderError::TokenizationError(format!("Failed to tokenize input: {}", e))
+            })?;
+
+        // gather all tokens, and average them per text with a (texts x tokens) matrix
+        let num_tokens: usize = encodings.iter().map(|e| e.get_ids().len()).sum();
+        let mut ids = Vec::with_capacity(num_tokens);
+        let mut pooling = vec![0f32; texts.len() * num_tokens];
+        for (row, encoding) in encodings.iter().enumerate() {
+            let len = encoding.get_ids().len();
+            for col in ids.len()..ids.len() + len {
+                pooling[row * num_tokens + col] = 1.0 / len as f32;
+            }
+            ids.extend_from_slice(encoding.get_ids());
+        }
+        let ids = Tensor::from_vec(ids, num_tokens, &self.device)?;
+        let pooling = Tensor::from_vec(pooling, (texts.len(), num_tokens), &self.device)?;
+        let pooled = pooling.matmul(&self.weights.as_tensor().index_select(&ids, 0)?)?;
+
+        let norm = pooled.sqr()?.sum_keepdim(1)?.affine(1.0, 1e-12)?.sqrt()?;
+        Ok(pooled.broadcast_div(&norm)?)
+    }
+
+    /// Save the adapted model as a local model directory that `ModelSpec::resolve` accepts
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        fs::create_dir_all(output_dir)?;
+        let tensors = HashMap::from([(
+            self.format.weights_tensor().to_string(),
+            self.weights.as_tensor().clone(),
+        )]);
+        candle::safetensors::save(&tensors, output_dir.join(WEIGHTS_FILE))?;
+        self.tokenizer
+            .save(output_dir.join(TOKENIZER_FILE), false)
+            .map_err(|e| anyhow::anyhow!("Failed to save tokenizer: {}", e))?;
+        Ok(())
+    }
+}
+
+/// Self-supervised pairs from two columns of the same row, e.g. (title, overview).
+/// Rows where either column is null or empty are left out.
+pub fn column_pairs(
+    batches: &[RecordBatch],
+    schema: &Schema,
+    anchor_column: &str,
+    positive_column: &str,
+) -> Result<Vec<(String, String)>> {
+    let anchor_idx = schema.index_of(anchor_column)?;
+    let positive_idx = schema.index_of(positive_column)?;
+    let mut pairs = Vec::new();
+    for batch in batches {
+        let (anchors, positives) = (batch.column(anchor_idx), batch.column(positive_idx));
+        for row in 0..batch.num_rows() {
+            if anchors.is_null(row) || positives.is_null(row) {
+                continue;
+            }
+            let anchor = extract_value_from_array(anchors.as_ref(), row);
+            let positive = extract_value_from_array(positives.as_ref(), row);
+            if !anchor.is_empty() && !positive.is_empty() {
+                pairs.push((anchor, positive));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+// Fisher-Yates shuffle driven by a xorshift generator, so runs are reproducible
+fn shuffle(values: &mut [usize], state: &mut u64) {
+    for i in (1..values.len()).rev() {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        values.swap(i, (*state % (i as u64 + 1)) as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::static_embeder::StaticEmbedder;
+    use arrow::array::StringArray;
+    use arrow::datatypes::{DataType, Field};
+    use std::sync::Arc;
+
+    const WORDS: [&str; 9] = [
+        "[UNK]", "red", "apple", "blue", "sky", "green", "grass", "yellow", "sun",
+    ];
+
+    // Write a local static model with a word level tokenizer over WORDS and spread out
+    // token embeddings of `dim` dimensions
+    fn write_model(dir: &Path, dim: usize) -> Result<()> {
+        let vocab: serde_json::Map<String, serde_json::Value> = WORDS
+            .iter()
+            .enumerate()
+            .map(|(id, word)| (word.to_string(), id.into()))
+            .collect();
+        let tokenizer = serde_json::json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [],
+            "normalizer": null,
+            "pre_tokenizer": {"type": "Whitespace"},
+            "post_processor": null,
+            "decoder": null,
+            "model": {"type": "WordLevel", "vocab": vocab, "unk_token": "[UNK]"},
+        });
+        fs::write(dir.join(TOKENIZER_FILE), tokenizer.to_string())?;
+
+        let weights: Vec<f32> = (0..WORDS.len() * dim)
+            .map(|i| ((i * 7919) % 17) as f32 / 8.0 - 1.0)
+            .collect();
+        let weights = Tensor::from_vec(weights, (WORDS.len(), dim), &Device::Cpu)?;
+        let tensors = HashMap::from([("embedding.weight".to_string(), weights)]);
+        candle::safetensors::save(&tensors, dir.join(WEIGHTS_FILE))?;
+        Ok(())
+    }
+
+    fn pairs() -> Vec<(String, String)> {
+        [
+            ("red", "apple"),
+            ("blue", "sky"),
+            ("green", "grass"),
+            ("yellow", "sun"),
+        ]
+        .iter()
+        .map(|(anchor, positive)| (anchor.to_string(), positive.to_string()))
+        .collect()
+    }
+
+    // every step sees all the pairs, so the epoch losses are comparable
+    fn config() -> FineTuneConfig {
+        FineTuneConfig {
+            epochs: 20,
+            learning_rate: 0.05,
+            batch_size: 4,
+            temperature: 0.1,
+            seed: 7,
+        }
+    }
+
+    fn similarity(embedder: &StaticEmbedder, a: &str, b: &str) -> Result<f32> {
+        let embeddings = embedder.embed_batch_flat(&[a, b])?;
+        let (a, b) = embeddings.split_at(embeddings.len() / 2);
+        Ok(a.iter().zip(b).map(|(x, y)| x * y).sum())
+    }
+
+    #[test]
+    fn test_train_lowers_loss() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_model(dir.path(), 8)?;
+        let tuner = FineTuner::from_spec(&ModelSpec::resolve(&dir.path().to_string_lossy())?)?;
+        let config = config();
+        let losses = tuner.train(&pairs(), &config)?;
+        assert_eq!(losses.len(), 20);
+        assert!(
+            losses[19] < losses[0],
+            "loss did not decrease: {:?}",
+            losses
+        );
+        assert!(tuner.train(&pairs()[..1], &config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_saved_model_loads_as_static_model() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        write_model(dir.path(), 8)?;
+        let spec = ModelSpec::resolve(&dir.path().to_string_lossy())?;
+        let tuner = FineTuner::from_spec(&spec)?;
+        let config = config();
+        tuner.train(&pairs(), &config)?;
+        let output = dir.path().join("tuned");
+        tuner.save(&output)?;
+
+        let original = StaticEmbedder::from_spec(&spec)?;
+        let tuned = StaticEmbedder::from_spec(&ModelSpec::resolve(&output.to_string_lossy())?)?;
+        assert_eq!(tuned.model_dim, 8);
+        // the pairs were pulled together by the training
+        assert!(similarity(&tuned, "red", "apple")? > similarity(&original, "red", "apple")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_pairs() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("title", DataType::Utf8, true),
+            Field::new("overview", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec![Some("Up"), None, Some("Heat")])),
+                Arc::new(StringArray::from(vec![
+                    Some("A balloon house"),
+                    Some("No title"),
+                    Some(""),
+                ])),
+            ],
+        )?;
+        let pairs = column_pairs(&[batch], &schema, "title", "overview")?;
+        assert_eq!(
+            pairs,
+            vec![("Up".to_string(), "A balloon house".to_string())]
+        );
+        assert!(column_pairs(&[], &schema, "title", "rating").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_shuffle_is_a_permutation() {
+        let mut values: Vec<usize> = (0..100).collect();
+        let mut state = 42;
+        shuffle(&mut values, &mut state);
+        assert_ne!(values, (0..100).collect::<Vec<_>>());
+        values.sort_unstable();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+    }
+}
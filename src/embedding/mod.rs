@@ -1,2 +1,9 @@
+pub mod bert_embeder;
 pub mod coordinator;
+pub mod embedder;
+pub mod fine_tune;
+pub mod model_download;
+pub mod projection;
+pub mod query_cache;
 pub mod static_embeder;
+pub mod token_weights;
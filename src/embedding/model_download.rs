@@ -0,0 +1,528 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::embedding::static_embeder::EmbedderError;
+
+/// Host the registered models are downloaded from
+pub const DEFAULT_BASE_URL: &str = "https://huggingface.co";
+/// Environment variable overriding the download host, e.g. with an internal mirror
+pub const BASE_URL_ENV: &str = "DFEMBED_MODEL_BASE_URL";
+/// Environment variable pointing at a manifest that is merged over the built-in one
+pub const MANIFEST_ENV: &str = "DFEMBED_MODEL_MANIFEST";
+
+const BUILTIN_MANIFEST: &str = include_str!("model_manifest.json");
+const LOCK_FILE: &str = ".download.lock";
+const VERIFIED_SUFFIX: &str = ".verified";
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+static MANIFEST: Lazy<Result<ModelManifest, String>> =
+    Lazy::new(|| ModelManifest::load().map_err(|e| e.to_string()));
+
+/// A registered model: the path of its files below the base url and their pinned checksums
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub sha256: HashMap<String, String>,
+}
+
+/// The models that can be selected by name
+#[derive(Debug, Clone, Default)]
+pub struct ModelManifest {
+    models: BTreeMap<String, ManifestEntry>,
+}
+
+impl ModelManifest {
+    /// Parse a manifest of the form `{"<model>": {"path": "...", "sha256": {"<file>": "<hex>"}}}`
+    pub fn parse(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let models = value
+            .as_object()
+            .ok_or_else(|| invalid_manifest("expected an object of models"))?;
+
+        let mut manifest = Self::default();
+        for (name, model) in models {
+            let path = model
+                .get("path")
+                .and_then(|path| path.as_str())
+                .ok_or_else(|| invalid_manifest(&format!("model {} has no path", name)))?;
+            let mut sha256 = HashMap::new();
+            if let Some(files) = model.get("sha256") {
+                let files = files.as_object().ok_or_else(|| {
+                    invalid_manifest(&format!("sha256 of model {} is not an object", name))
+                })?;
+                for (file, hash) in files {
+                    let hash = hash
+                        .as_str()
+                        .filter(|hash| is_sha256(hash))
+                        .ok_or_else(|| {
+                            invalid_manifest(&format!("invalid sha256 for {}/{}", name, file))
+                        })?;
+                    sha256.insert(file.clone(), hash.to_lowercase());
+                }
+            }
+            manifest.models.insert(
+                name.clone(),
+                ManifestEntry {
+                    path: path.trim_matches('/').to_string(),
+                    sha256,
+                },
+            );
+        }
+        Ok(manifest)
+    }
+
+    // the built-in manifest, extended by the manifest in DFEMBED_MODEL_MANIFEST if set
+    fn load() -> Result<Self> {
+        let mut manifest = Self::parse(BUILTIN_MANIFEST)?;
+        if let Ok(path) = std::env::var(MANIFEST_ENV) {
+            let extra = Self::parse(&fs::read_to_string(&path)?)?;
+            manifest.models.extend(extra.models);
+        }
+        Ok(manifest)
+    }
+
+    /// The manifest used to resolve model names, loaded once per process
+    pub fn global() -> Result<&'static ModelManifest> {
+        MANIFEST.as_ref().map_err(|e| {
+            EmbedderError::ModelLoadError(format!("Failed to load model manifest: {}", e)).into()
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        self.models.get(name)
+    }
+
+    pub fn model_names(&self) -> Vec<&str> {
+        self.models.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+fn invalid_manifest(message: &str) -> EmbedderError {
+    EmbedderError::ModelLoadError(format!("Invalid model manifest: {}", message))
+}
+
+fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// A failed download attempt, and whether trying again may succeed
+struct DownloadFailure {
+    message: String,
+    retryable: bool,
+}
+
+impl DownloadFailure {
+    fn retryable(e: impl Display) -> Self {
+        Self {
+            message: e.to_string(),
+            retryable: true,
+        }
+    }
+
+    fn fatal(e: impl Display) -> Self {
+        Self {
+            message: e.to_string(),
+            retryable: false,
+        }
+    }
+}
+
+/// Downloads model files with retries. Files are written to a temporary file and only
+/// renamed into place once their checksum is verified.
+pub struct ModelDownloader {
+    client: reqwest::blocking::Client,
+    base_url: String,
+    max_attempts: u32,
+    initial_backoff: Duration,
+}
+
+impl ModelDownloader {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::blocking::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            max_attempts: MAX_ATTEMPTS,
+            initial_backoff: INITIAL_BACKOFF,
+        }
+    }
+
+    /// Download from the base url in `DFEMBED_MODEL_BASE_URL`, or from the Hugging Face hub
+    pub fn from_env() -> Self {
+        let base_url = std::env::var(BASE_URL_ENV).unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        Self::new(&base_url)
+    }
+
+    /// Try each download up to `max_attempts` times, doubling the backoff after each failure
+    pub fn with_retries(mut self, max_attempts: u32, initial_backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    /// Make sure the given files of a model are present and verified in `model_dir`.
+    /// Files with a pinned checksum are checked against it, files already on disk included,
+    /// and downloaded anew when they do not match. A file is hashed once after it is written;
+    /// later fetches trust it while its size and modification time match that record.
+    /// The directory is locked while downloading, so concurrent processes fetch each file once.
+    pub fn fetch(&self, entry: &ManifestEntry, files: &[&str], model_dir: &Path) -> Result<()> {
+        fs::create_dir_all(model_dir)?;
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(model_dir.join(LOCK_FILE))?;
+        // released when the lock file is dropped
+        lock.lock()?;
+
+        for file in files {
+            let expected = entry.sha256.get(*file).map(|hash| hash.as_str());
+            if expected.is_none() {
+                warn!(
+                    "{}/{} has no pinned sha256 in the model manifest and is not verified",
+                    entry.path, file
+                );
+            }
+            let dest = model_dir.join(file);
+            // a truncated file or a saved error page is replaced rather than loaded
+            if dest.exists() {
+                if verify_existing(&dest, expected)? {
+                    continue;
+                }
+                warn!(
+                    "{} does not match its pinned checksum, downloading it again",
+                    dest.display()
+                );
+            }
+
+            let url = format!("{}/{}/{}", self.base_url, entry.path, file);
+            let part = model_dir.join(format!("{}.part", file));
+            info!("Downloading {}", url);
+            self.download_with_retries(&url, &part)?;
+            let sha256 = match verify_checksum(&part, file, expected) {
+                Ok(sha256) => sha256,
+                Err(e) => {
+                    fs::remove_file(&part)?;
+                    return Err(e);
+                }
+            };
+            fs::rename(&part, &dest)?;
+            VerifiedFile::of(&dest, sha256)?.save(&dest)?;
+        }
+        Ok(())
+    }
+
+    fn download_with_retries(&self, url: &str, dest: &Path) -> Result<()> {
+        let mut attempt = 1;
+        let mut backoff = self.initial_backoff;
+        loop {
+            match self.download(url, dest) {
+                Ok(()) => return Ok(()),
+                Err(failure) if failure.retryable && attempt < self.max_attempts => {
+                    warn!(
+                        "Download of {} failed (attempt {}/{}): {}, retrying in {:?}",
+                        url, attempt, self.max_attempts, failure.message, backoff
+                    );
+                    thread::sleep(backoff);
+                    attempt += 1;
+                    backoff *= 2;
+                }
+                Err(failure) => {
+                    if dest.exists() {
+                        fs::remove_file(dest)?;
+                    }
+                    return Err(EmbedderError::DownloadError(format!(
+                        "{} (after {} attempts): {}",
+                        url, attempt, failure.message
+                    ))
+                    .into());
+                }
+            }
+        }
+    }
+
+    fn download(&self, url: &str, dest: &Path) -> Result<(), DownloadFailure> {
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .map_err(DownloadFailure::retryable)?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = format!("server responded with {}", status);
+            // client errors such as 404 will not go away by asking again
+            return Err(
+                if status.is_server_error()
+                    || status == StatusCode::TOO_MANY_REQUESTS
+                    || status == StatusCode::REQUEST_TIMEOUT
+                {
+                    DownloadFailure::retryable(message)
+                } else {
+                    DownloadFailure::fatal(message)
+                },
+            );
+        }
+
+        let expected_len = response.content_length();
+        let mut file = fs::File::create(dest).map_err(DownloadFailure::fatal)?;
+        let written = io::copy(&mut response, &mut file).map_err(DownloadFailure::retryable)?;
+        if let Some(expected_len) = expected_len {
+            if written != expected_len {
+                return Err(DownloadFailure::retryable(format!(
+                    "download truncated after {} of {} bytes",
+                    written, expected_len
+                )));
+            }
+        }
+        file.sync_all().map_err(DownloadFailure::fatal)?;
+        Ok(())
+    }
+}
+
+/// The size, modification time and checksum of a model file when it was last hashed, kept
+/// next to it so that loading the model does not hash the file again
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VerifiedFile {
+    len: u64,
+    modified_ns: u128,
+    sha256: String,
+}
+
+impl VerifiedFile {
+    fn of(path: &Path, sha256: String) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            len: metadata.len(),
+            modified_ns: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos(),
+            sha256,
+        })
+    }
+
+    fn record_path(path: &Path) -> PathBuf {
+        let mut record = path.as_os_str().to_owned();
+        record.push(VERIFIED_SUFFIX);
+        PathBuf::from(record)
+    }
+
+    // the record of `path`, if any, written as "<len> <modified_ns> <sha256>"
+    fn load(path: &Path) -> Option<Self> {
+        let record = fs::read_to_string(Self::record_path(path)).ok()?;
+        let mut fields = record.split_whitespace();
+        Some(Self {
+            len: fields.next()?.parse().ok()?,
+            modified_ns: fields.next()?.parse().ok()?,
+            sha256: fields.next().filter(|hash| is_sha256(hash))?.to_string(),
+        })
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let record = format!("{} {} {}\n", self.len, self.modified_ns, self.sha256);
+        fs::write(Self::record_path(path), record)?;
+        Ok(())
+    }
+}
+
+// whether a file already on disk matches its pinned checksum, hashing it only when it
+// changed since it was last recorded
+fn verify_existing(path: &Path, expected: Option<&str>) -> Result<bool> {
+    let recorded = VerifiedFile::load(path);
+    let current = VerifiedFile::of(path, String::new())?;
+    let verified = match recorded {
+        Some(recorded)
+            if (recorded.len, recorded.modified_ns) == (current.len, current.modified_ns) =>
+        {
+            recorded
+        }
+        _ => {
+            let verified = VerifiedFile {
+                sha256: sha256_file(path)?,
+                ..current
+            };
+            verified.save(path)?;
+            verified
+        }
+    };
+    Ok(expected.is_none_or(|expected| verified.sha256 == expected))
+}
+
+// hashes a downloaded file, failing when it does not match its pinned checksum
+fn verify_checksum(path: &Path, file: &str, expected: Option<&str>) -> Result<String> {
+    let actual = sha256_file(path)?;
+    match expected {
+        Some(expected) if actual != expected => Err(EmbedderError::DownloadError(format!(
+            "Checksum mismatch for {}: expected sha256 {} but got {}",
+            file, expected, actual
+        ))
+        .into()),
+        _ => Ok(actual),
+    }
+}
+
+/// Hex encoded SHA-256 of a file
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Serve the given (status, body) responses in order, one per connection
+    fn serve(responses: Vec<(u16, &'static [u8])>) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            for (status, body) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+        (base_url, handle)
+    }
+
+    fn entry(sha256: Option<&[u8]>) -> ManifestEntry {
+        let mut hashes = HashMap::new();
+        if let Some(content) = sha256 {
+            hashes.insert(
+                "tokenizer.json".to_string(),
+                format!("{:x}", Sha256::digest(content)),
+            );
+        }
+        ManifestEntry {
+            path: "org/model/resolve/main".to_string(),
+            sha256: hashes,
+        }
+    }
+
+    #[test]
+    fn test_builtin_manifest() {
+        let manifest = ModelManifest::parse(BUILTIN_MANIFEST).unwrap();
+        assert!(
+            manifest
+                .get(crate::embedding::static_embeder::DEFAULT_MODEL)
+                .is_some()
+        );
+        assert!(ModelManifest::parse(r#"{"m": {"path": "p", "sha256": {"f": "abc"}}}"#).is_err());
+    }
+
+    #[test]
+    fn test_download_retries_and_verifies() -> Result<()> {
+        let body: &'static [u8] = b"{\"model\": {}}";
+        let (base_url, server) = serve(vec![(503, b"unavailable"), (200, body)]);
+        let dir = tempfile::tempdir()?;
+
+        ModelDownloader::new(&base_url)
+            .with_retries(3, Duration::from_millis(1))
+            .fetch(&entry(Some(body)), &["tokenizer.json"], dir.path())?;
+        server.join().unwrap();
+
+        assert_eq!(fs::read(dir.path().join("tokenizer.json"))?, body);
+        assert!(!dir.path().join("tokenizer.json.part").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_rejects_bad_responses() -> Result<()> {
+        // a 404 page is not retried and never becomes a model file
+        let (base_url, server) = serve(vec![(404, b"Not Found")]);
+        let dir = tempfile::tempdir()?;
+        let result = ModelDownloader::new(&base_url)
+            .with_retries(3, Duration::from_millis(1))
+            .fetch(&entry(Some(b"original")), &["tokenizer.json"], dir.path());
+        server.join().unwrap();
+        assert!(result.is_err());
+        assert!(!dir.path().join("tokenizer.json").exists());
+
+        // neither is a file that does not match its pinned checksum
+        let (base_url, server) = serve(vec![(200, b"tampered")]);
+        let result = ModelDownloader::new(&base_url)
+            .with_retries(1, Duration::from_millis(1))
+            .fetch(&entry(Some(b"original")), &["tokenizer.json"], dir.path());
+        server.join().unwrap();
+        assert!(result.is_err());
+        assert!(!dir.path().join("tokenizer.json").exists());
+        assert!(!dir.path().join("tokenizer.json.part").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_without_pinned_checksum() -> Result<()> {
+        let body: &'static [u8] = b"{\"model\": {}}";
+        let (base_url, server) = serve(vec![(200, body)]);
+        let dir = tempfile::tempdir()?;
+        ModelDownloader::new(&base_url)
+            .with_retries(1, Duration::from_millis(1))
+            .fetch(&entry(None), &["tokenizer.json"], dir.path())?;
+        server.join().unwrap();
+        assert_eq!(fs::read(dir.path().join("tokenizer.json"))?, body);
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_replaces_unverified_files() -> Result<()> {
+        let body: &'static [u8] = b"{\"model\": {}}";
+        let (base_url, server) = serve(vec![(200, body)]);
+        let dir = tempfile::tempdir()?;
+        // left behind by an interrupted download
+        fs::write(dir.path().join("tokenizer.json"), &body[..4])?;
+
+        let downloader = ModelDownloader::new(&base_url).with_retries(1, Duration::from_millis(1));
+        downloader.fetch(&entry(Some(body)), &["tokenizer.json"], dir.path())?;
+        server.join().unwrap();
+        assert_eq!(fs::read(dir.path().join("tokenizer.json"))?, body);
+
+        // a verified file is not downloaded again, the server is gone
+        downloader.fetch(&entry(Some(body)), &["tokenizer.json"], dir.path())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_verified_file_record() -> Result<()> {
+        let body: &'static [u8] = b"{\"model\": {}}";
+        let (base_url, server) = serve(vec![(200, body)]);
+        let dir = tempfile::tempdir()?;
+        let dest = dir.path().join("tokenizer.json");
+        ModelDownloader::new(&base_url)
+            .with_retries(1, Duration::from_millis(1))
+            .fetch(&entry(Some(body)), &["tokenizer.json"], dir.path())?;
+        server.join().unwrap();
+
+        let sha256 = format!("{:x}", Sha256::digest(body));
+        let recorded = VerifiedFile::load(&dest).expect("the download is recorded");
+        assert_eq!(recorded, VerifiedFile::of(&dest, sha256.clone())?);
+        assert!(verify_existing(&dest, Some(&sha256))?);
+
+        // a file changed since it was recorded is hashed again
+        fs::write(&dest, b"tampered")?;
+        assert!(!verify_existing(&dest, Some(&sha256))?);
+        assert!(verify_existing(&dest, None)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::Result;
+use candle::{Device, Tensor};
+use nalgebra::{DMatrix, SymmetricEigen};
+use safetensors::SafeTensors;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::embedding::embedder::Embedder;
+use crate::embedding::static_embeder::normalize_l2;
+
+const MEAN_TENSOR: &str = "mean";
+const COMPONENTS_TENSOR: &str = "components";
+
+/// Number of rows the projection is fitted on
+pub const PROJECTION_SAMPLE_SIZE: usize = 10_000;
+
+// Added to the variances before whitening, so near-constant directions are not blown up
+const WHITENING_EPSILON: f64 = 1e-6;
+
+/// Linear transform fitted on the vectors of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionKind {
+    /// Centering and rotation onto the principal axes
+    Pca,
+    /// PCA with every axis scaled to unit variance
+    Whiten,
+}
+
+impl ProjectionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectionKind::Pca => "pca",
+            ProjectionKind::Whiten => "whiten",
+        }
+    }
+}
+
+impl FromStr for ProjectionKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pca" => Ok(ProjectionKind::Pca),
+            "whiten" => Ok(ProjectionKind::Whiten),
+            other => Err(anyhow::anyhow!(
+                "Unknown projection '{}': expected 'pca' or 'whiten'",
+                other
+            )),
+        }
+    }
+}
+
+/// Centers vectors on the table mean and maps them onto the leading principal axes,
+/// optionally keeping fewer dimensions than the embedder produces
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projection {
+    pub kind: ProjectionKind,
+    mean: Vec<f32>,
+    // output_dim x input_dim, one principal axis per row
+    components: Vec<f32>,
+}
+
+impl Projection {
+    /// Fit on `vectors`, a flat buffer of `dim` sized vectors. Zero and non-finite vectors
+    /// are left out, and `output_dim` defaults to `dim`.
+    pub fn fit(
+        vectors: &[f32],
+        dim: usize,
+        kind: ProjectionKind,
+        output_dim: Option<usize>,
+    ) -> Result<Self> {
+        let output_dim = output_dim.unwrap_or(dim);
+        if output_dim == 0 || output_dim > dim {
+            return Err(anyhow::anyhow!(
+                "Projection dimension must be between 1 and {}, got {}",
+                dim,
+                output_dim
+            ));
+        }
+        let rows: Vec<&[f32]> = vectors.chunks_exact(dim).filter(|v| is_valid(v)).collect();
+        if rows.len() < 2 {
+            return Err(anyhow::anyhow!(
+                "Fitting a projection needs at least 2 valid vectors, got {}",
+                rows.len()
+            ));
+        }
+
+        let num_rows = rows.len();
+        let mut mean = vec![0f64; dim];
+        for row in &rows {
+            for (m, x) in mean.iter_mut().zip(row.iter()) {
+                *m += *x as f64;
+            }
+        }
+        mean.iter_mut().for_each(|m| *m /= num_rows as f64);
+        let centered: Vec<f64> = rows
+            .iter()
+            .flat_map(|row| row.iter().zip(&mean).map(|(x, m)| *x as f64 - m))
+            .collect();
+        let centered = Tensor::from_vec(centered, (num_rows, dim), &Device::Cpu)?;
+        let covariance = (centered.t()?.contiguous()?.matmul(&centered)? / (num_rows - 1) as f64)?;
+        let covariance: Vec<f64> = covariance.flatten_all()?.to_vec1()?;
+
+        // the eigenvalues come unsorted, with the unit eigenvectors as matching columns
+        let eigen = SymmetricEigen::new(DMatrix::from_row_slice(dim, dim, &covariance));
+        let (eigenvalues, eigenvectors) = (&eigen.eigenvalues, &eigen.eigenvectors);
+        let mut order: Vec<usize> = (0..dim).collect();
+        order.sort_by(|a, b| eigenvalues[*b].total_cmp(&eigenvalues[*a]));
+
+        let mut components = Vec::with_capacity(output_dim * dim);
+        for &axis in &order[..output_dim] {
+            let scale = match kind {
+                ProjectionKind::Pca => 1.0,
+                ProjectionKind::Whiten => {
+                    1.0 / (eigenvalues[axis].max(0.0) + WHITENING_EPSILON).sqrt()
+                }
+            };
+            let axis = eigenvectors.column(axis);
+            components.extend(axis.iter().map(|x| (x * scale) as f32));
+        }
+        Ok(Self {
+            kind,
+            mean: mean.into_iter().map(|m| m as f32).collect(),
+            components,
+        })
+    }
+
+    /// Dimension of the vectors the projection takes
+    pub fn input_dim(&self) -> usize {
+        self.mean.len()
+    }
+
+    /// Dimension of the projected vectors
+    pub fn output_dim(&self) -> usize {
+        self.components.len() / self.mean.len()
+    }
+
+    /// Project and L2 normalize a vector. Zero and non-finite vectors map to zero and NaN
+    /// vectors of the output dimension, so they are still recognized as invalid.
+    pub fn apply(&self, vector: &[f32]) -> Vec<f32> {
+        if vector.iter().all(|x| *x == 0.0) {
+            return vec![0.0; self.output_dim()];
+        }
+        if vector.iter().any(|x| !x.is_finite()) {
+            return vec![f32::NAN; self.output_dim()];
+        }
+        let centered: Vec<f32> = vector.iter().zip(&self.mean).map(|(x, m)| x - m).collect();
+        let mut projected: Vec<f32> = self
+            .components
+            .chunks_exact(self.input_dim())
+            .map(|axis| axis.iter().zip(&centered).map(|(a, x)| a * x).sum())
+            .collect();
+        normalize_l2(&mut projected);
+        projected
+    }
+
+    /// Save the mean and the projection matrix as a safetensors file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mean: Vec<u8> = self.mean.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let components: Vec<u8> = self
+            .components
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        let mean = safetensors::tensor::TensorView::new(
+            safetensors::Dtype::F32,
+            vec![self.input_dim()],
+            &mean,
+        )?;
+        let components = safetensors::tensor::TensorView::new(
+            safetensors::Dtype::F32,
+            vec![self.output_dim(), self.input_dim()],
+            &components,
+        )?;
+        safetensors::serialize_to_file(
+            [(MEAN_TENSOR, mean), (COMPONENTS_TENSOR, components)],
+            &None,
+            path,
+        )?;
+        Ok(())
+    }
+
+    /// Load a projection saved with [`Projection::save`]
+    pub fn load(path: &Path, kind: ProjectionKind) -> Result<Self> {
+        let data = fs::read(path)?;
+        let tensors = SafeTensors::deserialize(&data)?;
+        let read = |name: &str| -> Result<Vec<f32>> {
+            Ok(tensors
+                .tensor(name)?
+                .data()
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect())
+        };
+        let mean = read(MEAN_TENSOR)?;
+        let components = read(COMPONENTS_TENSOR)?;
+        if mean.is_empty() || components.len() % mean.len() != 0 {
+            return Err(anyhow::anyhow!(
+                "Invalid projection file {}",
+                path.display()
+            ));
+        }
+        Ok(Self {
+            kind,
+            mean,
+            components,
+        })
+    }
+}
+
+fn is_valid(vector: &[f32]) -> bool {
+    vector.iter().all(|x| x.is_finite()) && vector.iter().any(|x| *x != 0.0)
+}
+
+/// Applies the projection fitted on a table to the vectors of an embedder
+pub struct ProjectedEmbedder {
+    inner: Arc<dyn Embedder>,
+    projection: Arc<Projection>,
+}
+
+impl ProjectedEmbedder {
+    pub fn new(inner: Arc<dyn Embedder>, projection: Arc<Projection>) -> Self {
+        Self { inner, projection }
+    }
+}
+
+impl Embedder for ProjectedEmbedder {
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn embedding_dim(&self) -> usize {
+        self.projection.output_dim()
+    }
+
+    fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        Ok(self
+            .inner
+            .embed_batch_vec(texts)?
+            .iter()
+            .map(|embedding| self.projection.apply(embedding))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // correlated 3d points spread along (1, 1, 0)
+    fn sample() -> Vec<f32> {
+        (0..200)
+            .flat_map(|i| {
+                let t = (i as f32 / 20.0).sin() * 3.0;
+                let noise = (i as f32 * 1.7).cos() * 0.1;
+                vec![1.0 + t + noise, 2.0 + t - noise, 0.5 + noise]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_and_apply() -> Result<()> {
+        let vectors = sample();
+        let pca = Projection::fit(&vectors, 3, ProjectionKind::Pca, Some(2))?;
+        assert_eq!((pca.input_dim(), pca.output_dim()), (3, 2));
+        // the first axis follows the main direction of the points
+        let axis = &pca.components[..3];
+        assert!((axis[0].abs() - axis[1].abs()).abs() < 0.05);
+        assert!(axis[2].abs() < 0.1);
+
+        let projected = pca.apply(&vectors[..3]);
+        let norm: f32 = projected.iter().map(|x| x * x).sum();
+        assert_eq!(projected.len(), 2);
+        assert!((norm - 1.0).abs() < 1e-5);
+        // invalid vectors stay invalid
+        assert_eq!(pca.apply(&[0.0, 0.0, 0.0]), vec![0.0, 0.0]);
+        assert!(pca.apply(&[f32::NAN, 1.0, 0.0])[0].is_nan());
+
+        // whitened components have unit variance over the sample
+        let whiten = Projection::fit(&vectors, 3, ProjectionKind::Whiten, None)?;
+        let mut variance = [0f64; 3];
+        for row in vectors.chunks_exact(3) {
+            for (axis, var) in whiten.components.chunks_exact(3).zip(variance.iter_mut()) {
+                let x: f32 = (0..3).map(|k| axis[k] * (row[k] - whiten.mean[k])).sum();
+                *var += (x * x) as f64 / 199.0;
+            }
+        }
+        assert!((variance[0] - 1.0).abs() < 1e-2);
+        assert!((variance[1] - 1.0).abs() < 1e-2);
+
+        assert!(Projection::fit(&vectors, 3, ProjectionKind::Pca, Some(4)).is_err());
+        assert!(Projection::fit(&vectors[..3], 3, ProjectionKind::Pca, None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_projection_save_load() -> Result<()> {
+        let projection = Projection::fit(&sample(), 3, ProjectionKind::Whiten, Some(2))?;
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("projection.safetensors");
+        projection.save(&path)?;
+        assert_eq!(Projection::load(&path, ProjectionKind::Whiten)?, projection);
+        Ok(())
+    }
+}
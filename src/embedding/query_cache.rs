@@ -0,0 +1,204 @@
+use anyhow::Result;
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::embedding::embedder::Embedder;
+use crate::embedding::token_weights::{TokenWeighting, TokenWeights};
+
+/// Default number of query embeddings kept in the cache
+pub const DEFAULT_QUERY_CACHE_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct QueryKey {
+    model_id: String,
+    dim: usize,
+    // fingerprint of the token weights the query was pooled with, if any
+    token_weights: Option<u64>,
+    text: String,
+}
+
+/// Snapshot of the cache counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// Bounded LRU cache of query embeddings in front of an embedder.
+/// Safe to share between threads; only the texts missing from the cache are embedded.
+pub struct CachedEmbedder {
+    inner: Arc<dyn Embedder>,
+    cache: Mutex<LruCache<QueryKey, Vec<f32>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachedEmbedder {
+    pub fn new(inner: Arc<dyn Embedder>, capacity: NonZeroUsize) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(LruCache::new(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.lock_cache();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: cache.len(),
+            capacity: cache.cap().get(),
+        }
+    }
+
+    /// Drop all cached embeddings and reset the counters
+    pub fn clear(&self) {
+        self.lock_cache().clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    // entries are inserted whole, so the cache is consistent even if a holder panicked
+    fn lock_cache(&self) -> MutexGuard<'_, LruCache<QueryKey, Vec<f32>>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn key(&self, text: &str, weights: Option<&TokenWeights>) -> QueryKey {
+        QueryKey {
+            model_id: self.inner.model_id().to_string(),
+            dim: self.inner.embedding_dim(),
+            token_weights: weights.map(|w| w.fingerprint()),
+            text: text.to_string(),
+        }
+    }
+
+    fn embed_cached(
+        &self,
+        texts: &[&str],
+        weights: Option<&TokenWeights>,
+        embed: impl FnOnce(&[&str]) -> Result<Vec<Vec<f32>>>,
+    ) -> Result<Vec<Vec<f32>>> {
+        let keys: Vec<QueryKey> = texts.iter().map(|text| self.key(text, weights)).collect();
+        let mut results: Vec<Option<Vec<f32>>> = {
+            let mut cache = self.lock_cache();
+            keys.iter().map(|key| cache.get(key).cloned()).collect()
+        };
+        let missing: Vec<usize> = (0..texts.len()).filter(|&i| results[i].is_none()).collect();
+        let num_hits = texts.len() - missing.len();
+        self.hits.fetch_add(num_hits as u64, Ordering::Relaxed);
+        self.misses
+            .fetch_add(missing.len() as u64, Ordering::Relaxed);
+        if missing.is_empty() {
+            return Ok(results.into_iter().flatten().collect());
+        }
+
+        // embed outside the lock so concurrent hits are not blocked
+        let missing_texts: Vec<&str> = missing.iter().map(|&i| texts[i]).collect();
+        let embeddings = embed(&missing_texts)?;
+        if embeddings.len() != missing.len() {
+            return Err(anyhow::anyhow!(
+                "Embedder returned {} vectors for {} texts",
+                embeddings.len(),
+                missing.len()
+            ));
+        }
+        let mut cache = self.lock_cache();
+        for (&i, embedding) in missing.iter().zip(embeddings) {
+            cache.put(keys[i].clone(), embedding.clone());
+            results[i] = Some(embedding);
+        }
+        Ok(results.into_iter().flatten().collect())
+    }
+}
+
+impl Embedder for CachedEmbedder {
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn embedding_dim(&self) -> usize {
+        self.inner.embedding_dim()
+    }
+
+    fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.embed_cached(texts, None, |texts| self.inner.embed_batch_vec(texts))
+    }
+
+    fn fit_token_weights(&self, texts: &[&str], weighting: TokenWeighting) -> Result<TokenWeights> {
+        self.inner.fit_token_weights(texts, weighting)
+    }
+
+    fn embed_batch_vec_weighted(
+        &self,
+        texts: &[&str],
+        weights: &TokenWeights,
+    ) -> Result<Vec<Vec<f32>>> {
+        self.embed_cached(texts, Some(weights), |texts| {
+            self.inner.embed_batch_vec_weighted(texts, weights)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Embeds a text as its length, counting the texts it was asked to embed
+    struct CountingEmbedder {
+        calls: AtomicUsize,
+    }
+
+    impl Embedder for CountingEmbedder {
+        fn model_id(&self) -> &str {
+            "counting"
+        }
+
+        fn embedding_dim(&self) -> usize {
+            1
+        }
+
+        fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            self.calls.fetch_add(texts.len(), Ordering::Relaxed);
+            Ok(texts.iter().map(|text| vec![text.len() as f32]).collect())
+        }
+    }
+
+    #[test]
+    fn test_cached_embedder() -> Result<()> {
+        let inner = Arc::new(CountingEmbedder {
+            calls: AtomicUsize::new(0),
+        });
+        let cached = CachedEmbedder::new(inner.clone(), NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(
+            cached.embed_batch_vec(&["a", "bb"])?,
+            vec![vec![1.0], vec![2.0]]
+        );
+        // only the new text is embedded, and the results keep the input order
+        assert_eq!(
+            cached.embed_batch_vec(&["ccc", "a"])?,
+            vec![vec![3.0], vec![1.0]]
+        );
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 3);
+
+        let stats = cached.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+        assert_eq!((stats.len, stats.capacity), (2, 2));
+
+        // "bb" was the least recently used entry and has been evicted
+        cached.embed_batch_vec(&["bb"])?;
+        assert_eq!(inner.calls.load(Ordering::Relaxed), 4);
+
+        cached.clear();
+        let stats = cached.stats();
+        assert_eq!((stats.hits, stats.misses, stats.len), (0, 0, 0));
+        Ok(())
+    }
+}
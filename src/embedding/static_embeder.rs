@@ -1,19 +1,25 @@
 use anyhow::Result;
 use candle::{DType, Device, Tensor};
+use half::f16;
+use memmap2::Mmap;
 use ndarray::{Array1, Array2};
 use safetensors::SafeTensors;
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use thiserror::Error;
-use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer};
+use tokenizers::{Encoding, Tokenizer};
 use tracing::debug;
 
-const MODEL_URL_BASE: &str = "https://huggingface.co/sentence-transformers/static-retrieval-mrl-en-v1/resolve/main/0_StaticEmbedding";
-const MODEL_FILES: [&str; 2] = ["model.safetensors", "tokenizer.json"];
+use crate::embedding::embedder::Embedder;
+use crate::embedding::model_download::{ManifestEntry, ModelDownloader, ModelManifest};
+use crate::embedding::token_weights::{TokenCounts, TokenWeighting, TokenWeights};
 
-// Default embedding dimension for the model
-const DEFAULT_EMBEDDING_DIM: usize = 1024;
+pub const DEFAULT_MODEL: &str = "static-retrieval-mrl-en-v1";
+const MODEL_FILES: [&str; 2] = ["model.safetensors", "tokenizer.json"];
+const MODELS_DIR: &str = "models";
+// Number of rows tokenized at once when fitting token weights
+const FIT_CHUNK_SIZE: usize = 10_000;
 
 #[derive(Error, Debug)]
 pub enum EmbedderError {
@@ -27,205 +33,612 @@ pub enum EmbedderError {
     TokenizationError(String),
 }
 
-pub struct Embedder {
-    _model_path: PathBuf,
-    embedding_weights: Tensor,
-    tokenizer: Tokenizer,
-    pub embedding_dim: usize,
-    device: Device,
+/// The on-disk layout of a static embedding model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelFormat {
+    /// sentence-transformers `StaticEmbedding` module (weights under `embedding.weight`)
+    SentenceTransformers,
+    /// model2vec model (weights under `embeddings`, tokenized without special tokens)
+    Model2Vec,
 }
 
-impl Embedder {
-    /// Create a new Embedder instance
-    pub fn new() -> Result<Self> {
-        let model_path = Self::ensure_model_files()?;
+impl ModelFormat {
+    pub(crate) fn weights_tensor(&self) -> &'static str {
+        match self {
+            ModelFormat::SentenceTransformers => "embedding.weight",
+            ModelFormat::Model2Vec => "embeddings",
+        }
+    }
 
-        // Load tokenizer
-        let tokenizer_path = model_path.join("tokenizer.json");
-        let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| {
-            EmbedderError::ModelLoadError(format!("Failed to load tokenizer: {}", e))
-        })?;
+    pub(crate) fn add_special_tokens(&self) -> bool {
+        match self {
+            ModelFormat::SentenceTransformers => true,
+            ModelFormat::Model2Vec => false,
+        }
+    }
 
-        // Configure tokenizer padding
-        let pp = PaddingParams {
-            strategy: PaddingStrategy::BatchLongest,
-            ..Default::default()
+    /// Detect the format from the tensor names stored in the safetensors file
+    fn detect(tensors: &SafeTensors) -> Result<Self> {
+        let names = tensors.names();
+        [ModelFormat::SentenceTransformers, ModelFormat::Model2Vec]
+            .into_iter()
+            .find(|format| names.iter().any(|name| *name == format.weights_tensor()))
+            .ok_or_else(|| {
+                EmbedderError::ModelLoadError(format!(
+                    "No embedding tensor found in model weights (tensors: {:?})",
+                    names
+                ))
+                .into()
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ModelLocation {
+    Remote(ManifestEntry),
+    Local(PathBuf),
+}
+
+/// Identifies a static embedding model, either by a registered name or by a local directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSpec {
+    pub id: String,
+    location: ModelLocation,
+    // overrides the download host of registered models
+    base_url: Option<String>,
+}
+
+impl Default for ModelSpec {
+    fn default() -> Self {
+        Self::resolve(DEFAULT_MODEL).expect("default model is registered")
+    }
+}
+
+impl ModelSpec {
+    /// Resolve a model name or a path to a local model directory.
+    ///
+    /// A local directory must contain `model.safetensors` and `tokenizer.json`.
+    pub fn resolve(name_or_path: &str) -> Result<Self> {
+        let manifest = ModelManifest::global()?;
+        if let Some(entry) = manifest.get(name_or_path) {
+            return Ok(Self {
+                id: name_or_path.to_string(),
+                location: ModelLocation::Remote(entry.clone()),
+                base_url: None,
+            });
+        }
+
+        let path = Path::new(name_or_path);
+        if path.is_dir() {
+            for file in MODEL_FILES.iter() {
+                if !path.join(file).exists() {
+                    return Err(EmbedderError::ModelLoadError(format!(
+                        "Local model directory {} is missing {}",
+                        path.display(),
+                        file
+                    ))
+                    .into());
+                }
+            }
+            return Ok(Self {
+                id: name_or_path.to_string(),
+                location: ModelLocation::Local(path.to_path_buf()),
+                base_url: None,
+            });
+        }
+
+        Err(EmbedderError::ModelLoadError(format!(
+            "Unknown model '{}': expected one of {:?} or a path to a local model directory",
+            name_or_path,
+            manifest.model_names()
+        ))
+        .into())
+    }
+
+    /// Download registered models from `base_url` instead of the default host
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    /// Ensure model files are available locally and return the path to the model directory
+    pub(crate) fn ensure_model_files(&self) -> Result<PathBuf> {
+        let entry = match &self.location {
+            ModelLocation::Local(path) => return Ok(path.clone()),
+            ModelLocation::Remote(entry) => entry,
+        };
+        let model_dir = PathBuf::from(MODELS_DIR).join(&self.id);
+        let downloader = match &self.base_url {
+            Some(base_url) => ModelDownloader::new(base_url),
+            None => ModelDownloader::from_env(),
         };
-        tokenizer.with_padding(Some(pp));
+        downloader.fetch(entry, &MODEL_FILES, &model_dir)?;
+        Ok(model_dir)
+    }
+}
 
-        // Load embedding weights from safetensors
-        let device = Device::Cpu;
-        let weights_path = model_path.join("model.safetensors");
+/// Precision of the in-memory embedding table. Pooling always accumulates in f32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WeightPrecision {
+    #[default]
+    F32,
+    /// Half precision, halving the table size
+    F16,
+    /// 8-bit integers with a per-row f32 scale, a quarter of the table size
+    Int8,
+}
 
-        // Read the safetensors file
-        let data = fs::read(&weights_path)?;
-        let tensors = SafeTensors::deserialize(&data)?;
+impl FromStr for WeightPrecision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "f32" => Ok(WeightPrecision::F32),
+            "f16" => Ok(WeightPrecision::F16),
+            "int8" => Ok(WeightPrecision::Int8),
+            other => Err(anyhow::anyhow!(
+                "Unknown weight precision '{}': expected 'f32', 'f16' or 'int8'",
+                other
+            )),
+        }
+    }
+}
+
+/// Row-major `vocab_size x model_dim` embedding table
+enum EmbeddingWeights {
+    /// Weights read in place from the memory-mapped safetensors file
+    Mapped {
+        mmap: Mmap,
+        offset: usize,
+        len: usize,
+    },
+    /// Weights converted from another dtype (or an unaligned file) into memory
+    Owned(Vec<f32>),
+    F16(Vec<f16>),
+    /// Quantized rows, each dequantized as `value * scales[row]`
+    Int8 {
+        values: Vec<i8>,
+        scales: Vec<f32>,
+    },
+}
+
+impl EmbeddingWeights {
+    /// Memory-map the weights file, returning the mapping and the embedding tensor.
+    ///
+    /// f32 weights are used in place when the tensor data is aligned, so the table is shared
+    /// through the page cache instead of being copied into each process.
+    fn load(weights_path: &Path) -> Result<(Self, ModelFormat, Vec<usize>)> {
+        let file = fs::File::open(weights_path)?;
+        // SAFETY: the model files are not modified while the embedder is alive
+        let mmap = unsafe { Mmap::map(&file)? };
+        let tensors = SafeTensors::deserialize(&mmap)?;
+        let format = ModelFormat::detect(&tensors)?;
 
         // Get the embedding weights tensor
-        let embedding_tensor = tensors.tensor("embedding.weight")?;
-        let shape = embedding_tensor.shape();
+        let embedding_tensor = tensors.tensor(format.weights_tensor())?;
+        let shape = embedding_tensor.shape().to_vec();
         debug!("Loaded embedding weights with shape: {:?}", shape);
+        if shape.len() != 2 {
+            return Err(EmbedderError::ModelLoadError(format!(
+                "Expected 2-D embedding weights, got shape {:?}",
+                shape
+            ))
+            .into());
+        }
 
-        // Convert to candle tensor
-        let embedding_weights = Tensor::from_raw_buffer(
-            embedding_tensor.data(),
-            DType::F32,
-            &shape.to_vec(),
-            &device,
-        )?;
+        let data = embedding_tensor.data();
+        let aligned = data.as_ptr().align_offset(std::mem::align_of::<f32>()) == 0;
+        if embedding_tensor.dtype() == safetensors::Dtype::F32
+            && aligned
+            && cfg!(target_endian = "little")
+        {
+            let offset = data.as_ptr() as usize - mmap.as_ptr() as usize;
+            let len = data.len() / std::mem::size_of::<f32>();
+            drop(tensors);
+            return Ok((
+                EmbeddingWeights::Mapped { mmap, offset, len },
+                format,
+                shape,
+            ));
+        }
 
-        Ok(Self {
-            _model_path: model_path,
-            embedding_weights,
-            tokenizer,
-            embedding_dim: DEFAULT_EMBEDDING_DIM,
-            device,
-        })
+        // Convert to a contiguous f32 buffer
+        let dtype = match embedding_tensor.dtype() {
+            safetensors::Dtype::F32 => DType::F32,
+            safetensors::Dtype::F16 => DType::F16,
+            safetensors::Dtype::BF16 => DType::BF16,
+            safetensors::Dtype::F64 => DType::F64,
+            dt => {
+                return Err(EmbedderError::ModelLoadError(format!(
+                    "Unsupported embedding weights dtype: {:?}",
+                    dt
+                ))
+                .into());
+            }
+        };
+        let weights = Tensor::from_raw_buffer(data, dtype, &shape, &Device::Cpu)?
+            .to_dtype(DType::F32)?
+            .flatten_all()?
+            .to_vec1::<f32>()?;
+        Ok((EmbeddingWeights::Owned(weights), format, shape))
     }
 
-    /// Ensure model files are downloaded and return the path to the model directory
-    fn ensure_model_files() -> Result<PathBuf> {
-        let model_dir = PathBuf::from("models/static-retrieval-mrl-en-v1");
-
-        if !model_dir.exists() {
-            fs::create_dir_all(&model_dir)?;
+    /// The f32 table, if the weights are kept at full precision
+    fn as_f32(&self) -> Option<&[f32]> {
+        match self {
+            EmbeddingWeights::Mapped { mmap, offset, len } => {
+                let bytes = &mmap[*offset..*offset + *len * std::mem::size_of::<f32>()];
+                // SAFETY: alignment and length were checked when the weights were loaded,
+                // and any bit pattern is a valid f32
+                Some(unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, *len) })
+            }
+            EmbeddingWeights::Owned(weights) => Some(weights),
+            EmbeddingWeights::F16(_) | EmbeddingWeights::Int8 { .. } => None,
         }
+    }
 
-        for file in MODEL_FILES.iter() {
-            let file_path = model_dir.join(file);
-
-            if !file_path.exists() {
-                println!("Downloading {}", file);
-                let url = format!("{}/{}", MODEL_URL_BASE, file);
-                let response = reqwest::blocking::get(&url).map_err(|e| {
-                    EmbedderError::DownloadError(format!("Failed to download {}: {}", file, e))
-                })?;
-
-                let content = response.bytes().map_err(|e| {
-                    EmbedderError::DownloadError(format!(
-                        "Failed to read response for {}: {}",
-                        file, e
-                    ))
-                })?;
-
-                let parent_dir = file_path.parent().unwrap();
-                if !parent_dir.exists() {
-                    fs::create_dir_all(parent_dir)?;
+    /// Convert full precision weights to the requested precision
+    fn with_precision(self, precision: WeightPrecision, row_len: usize) -> Self {
+        match (precision, self.as_f32()) {
+            (WeightPrecision::F16, Some(weights)) => {
+                EmbeddingWeights::F16(weights.iter().map(|w| f16::from_f32(*w)).collect())
+            }
+            (WeightPrecision::Int8, Some(weights)) => {
+                let mut values = Vec::with_capacity(weights.len());
+                let mut scales = Vec::with_capacity(weights.len() / row_len);
+                for row in weights.chunks_exact(row_len) {
+                    let max_abs = row.iter().fold(0f32, |max, w| max.max(w.abs()));
+                    let scale = if max_abs > 0.0 { max_abs / 127.0 } else { 1.0 };
+                    values.extend(row.iter().map(|w| (w / scale).round() as i8));
+                    scales.push(scale);
                 }
-
-                let mut file = fs::File::create(&file_path)?;
-                file.write_all(&content)?;
+                EmbeddingWeights::Int8 { values, scales }
             }
+            _ => self,
         }
+    }
 
-        Ok(model_dir)
+    fn vocab_size(&self, row_len: usize) -> usize {
+        match self {
+            EmbeddingWeights::Mapped { len, .. } => len / row_len,
+            EmbeddingWeights::Owned(weights) => weights.len() / row_len,
+            EmbeddingWeights::F16(weights) => weights.len() / row_len,
+            EmbeddingWeights::Int8 { scales, .. } => scales.len(),
+        }
     }
 
-    /// Embed a single string (prefixed to silence unused warning)
-    pub fn _embed(&self, text: &str) -> Result<Array1<f32>> {
-        // First get the tensor embedding
-        let tensor_embeddings = self.embed_batch_tensor(&[text])?;
+    /// Sum the embedding rows of the given tokens into `pooled`, accumulating in f32.
+    /// Rows are scaled by `token_weights` when given.
+    fn pool(
+        &self,
+        row_len: usize,
+        token_ids: &[u32],
+        token_weights: Option<&[f32]>,
+        pooled: &mut [f32],
+    ) -> Result<()> {
+        match self {
+            EmbeddingWeights::Mapped { .. } | EmbeddingWeights::Owned(_) => {
+                let weights = self.as_f32().unwrap_or_default();
+                pool_tokens(
+                    weights,
+                    row_len,
+                    token_ids,
+                    token_weights,
+                    pooled,
+                    |pooled, row, _, token_weight| {
+                        for (acc, weight) in pooled.iter_mut().zip(row) {
+                            *acc += *weight * token_weight;
+                        }
+                    },
+                )
+            }
+            EmbeddingWeights::F16(weights) => pool_tokens(
+                weights,
+                row_len,
+                token_ids,
+                token_weights,
+                pooled,
+                |pooled, row, _, token_weight| {
+                    for (acc, weight) in pooled.iter_mut().zip(row) {
+                        *acc += weight.to_f32() * token_weight;
+                    }
+                },
+            ),
+            EmbeddingWeights::Int8 { values, scales } => pool_tokens(
+                values,
+                row_len,
+                token_ids,
+                token_weights,
+                pooled,
+                |pooled, row, token_id, token_weight| {
+                    let scale = scales[token_id] * token_weight;
+                    for (acc, value) in pooled.iter_mut().zip(row) {
+                        *acc += *value as f32 * scale;
+                    }
+                },
+            ),
+        }
+    }
+}
 
-        // Convert to ndarray for compatibility with existing code
-        let embedding = tensor_embeddings.get(0)?;
-        let embedding_vec = embedding.to_vec1::<f32>()?;
-        let array_embedding = Array1::from_vec(embedding_vec);
+/// Static embedding model. Loading memory-maps the weights once; the embedder is shared
+/// between worker threads behind an `Arc`.
+pub struct StaticEmbedder {
+    _model_path: PathBuf,
+    embedding_weights: EmbeddingWeights,
+    tokenizer: Tokenizer,
+    pub model_id: String,
+    pub format: ModelFormat,
+    /// Dimension of the embeddings returned by this embedder
+    pub embedding_dim: usize,
+    /// Dimension of the model weights
+    pub model_dim: usize,
+}
 
-        Ok(array_embedding)
+impl StaticEmbedder {
+    /// Create a new StaticEmbedder instance using the default model
+    pub fn new() -> Result<Self> {
+        Self::from_spec(&ModelSpec::default())
     }
 
-    /// Embed a batch of strings and return as ndarray (for compatibility)
-    pub fn embed_batch(&self, texts: &[&str]) -> Result<Array2<f32>> {
-        // Get tensor embeddings
-        let tensor_embeddings = self.embed_batch_tensor(texts)?;
+    /// Create a new StaticEmbedder instance for the given model
+    pub fn from_spec(spec: &ModelSpec) -> Result<Self> {
+        let model_path = spec.ensure_model_files()?;
 
-        // Convert to ndarray for compatibility
-        // Get dimensions manually
-        let batch_size = texts.len();
-        let flat_data = tensor_embeddings.flatten_all()?.to_vec1::<f32>()?;
+        // Load tokenizer
+        let tokenizer_path = model_path.join("tokenizer.json");
+        let mut tokenizer = Tokenizer::from_file(tokenizer_path).map_err(|e| {
+            EmbedderError::ModelLoadError(format!("Failed to load tokenizer: {}", e))
+        })?;
 
-        // The embedding dimension should be flat_data.len() / batch_size
-        let embedding_dim = if batch_size > 0 {
-            flat_data.len() / batch_size
-        } else {
-            self.embedding_dim
-        };
+        // Sentences are pooled one by one, so no padding is needed
+        tokenizer.with_padding(None);
+        // model2vec tokenizers may ship with truncation settings meant for transformer models
+        tokenizer
+            .with_truncation(None)
+            .map_err(|e| EmbedderError::ModelLoadError(format!("{}", e)))?;
 
-        let array_embeddings = Array2::from_shape_vec((batch_size, embedding_dim), flat_data)?;
+        // Load embedding weights from safetensors
+        let weights_path = model_path.join("model.safetensors");
+        let (embedding_weights, format, shape) = EmbeddingWeights::load(&weights_path)?;
 
-        Ok(array_embeddings)
+        Ok(Self {
+            _model_path: model_path,
+            embedding_weights,
+            tokenizer,
+            model_id: spec.id.clone(),
+            format,
+            embedding_dim: shape[1],
+            model_dim: shape[1],
+        })
     }
 
-    /// Embed a batch of strings and return as Vec<Vec<f32>>
-    pub fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
-        // Get tensor embeddings
-        let tensor_embeddings = self.embed_batch_tensor(texts)?;
+    /// Truncate the embeddings to the first `dim` dimensions (Matryoshka truncation).
+    ///
+    /// The static models are MRL-trained so the leading dimensions carry most of the
+    /// information; truncated embeddings are re-normalized after pooling.
+    pub fn with_output_dim(mut self, dim: usize) -> Result<Self> {
+        if dim == 0 || dim > self.model_dim {
+            return Err(EmbedderError::ModelLoadError(format!(
+                "Invalid output dimension {}: model {} has {} dimensions",
+                dim, self.model_id, self.model_dim
+            ))
+            .into());
+        }
+        self.embedding_dim = dim;
+        Ok(self)
+    }
 
-        let result = tensor_embeddings.to_vec2::<f32>()?; // Direct conversion
+    /// Keep the embedding table in memory at a reduced precision
+    pub fn with_precision(mut self, precision: WeightPrecision) -> Self {
+        self.embedding_weights = self
+            .embedding_weights
+            .with_precision(precision, self.model_dim);
+        self
+    }
 
-        Ok(result)
+    /// Embed a single string (prefixed to silence unused warning)
+    pub fn _embed(&self, text: &str) -> Result<Array1<f32>> {
+        Ok(Array1::from_vec(self.embed_batch_flat(&[text])?))
     }
 
-    /// Embed a batch of strings and return as Tensor
-    fn embed_batch_tensor(&self, texts: &[&str]) -> Result<Tensor> {
-        // Tokenize the inputs
-        let tokens = self
+    /// Tokenize the inputs
+    fn encode(&self, texts: &[&str]) -> Result<Vec<Encoding>> {
+        let encodings = self
             .tokenizer
-            .encode_batch(texts.iter().map(|s| s.to_string()).collect(), true)
+            .encode_batch_fast(texts.to_vec(), self.format.add_special_tokens())
             .map_err(|e| {
                 EmbedderError::TokenizationError(format!("Failed to tokenize input: {}", e))
             })?;
+        Ok(encodings)
+    }
 
-        let mut embeddings = Vec::with_capacity(texts.len());
+    /// Embed a batch of strings into a row-major buffer of `texts.len() * embedding_dim` floats
+    pub fn embed_batch_flat(&self, texts: &[&str]) -> Result<Vec<f32>> {
+        self.embed_batch_flat_weighted(texts, None)
+    }
 
-        for encoding in &tokens {
-            let input_ids = encoding.get_ids();
-            let attention_mask = encoding.get_attention_mask();
+    /// Embed a batch of strings, optionally weighting each token by `token_weights`
+    fn embed_batch_flat_weighted(
+        &self,
+        texts: &[&str],
+        token_weights: Option<&TokenWeights>,
+    ) -> Result<Vec<f32>> {
+        let mut embeddings = vec![0f32; texts.len() * self.embedding_dim];
+        self.pool_batch(texts, token_weights, &mut embeddings)?;
+        Ok(embeddings)
+    }
 
-            // Get embeddings for each token
-            let mut token_embeddings = Vec::new();
-            for (i, &token_id) in input_ids.iter().enumerate() {
-                if i < attention_mask.len() && attention_mask[i] == 1 {
-                    // Get embedding for this token from the embedding table
-                    let token_embedding = self.embedding_weights.get(token_id as usize)?;
-                    token_embeddings.push(token_embedding);
-                }
-            }
+    /// Pool a batch of strings into `embeddings`, one row of `embedding_dim` floats each
+    fn pool_batch(
+        &self,
+        texts: &[&str],
+        token_weights: Option<&TokenWeights>,
+        embeddings: &mut [f32],
+    ) -> Result<()> {
+        if embeddings.len() != texts.len() * self.embedding_dim {
+            return Err(anyhow::anyhow!(
+                "Buffer of {} floats cannot hold {} embeddings of {} dimensions",
+                embeddings.len(),
+                texts.len(),
+                self.embedding_dim
+            ));
+        }
+        let encodings = self.encode(texts)?;
+        let token_weights = token_weights.map(|weights| weights.as_slice());
+
+        for (encoding, embedding) in encodings
+            .iter()
+            .zip(embeddings.chunks_exact_mut(self.embedding_dim))
+        {
+            // the pooling adds to the buffer, which may hold a previous batch
+            embedding.fill(0.0);
+            self.embedding_weights.pool(
+                self.model_dim,
+                encoding.get_ids(),
+                token_weights,
+                embedding,
+            )?;
+            normalize_l2(embedding);
+        }
+        Ok(())
+    }
+}
 
-            // Stack token embeddings
-            if token_embeddings.is_empty() {
-                // If no tokens, create a zero embedding
-                let zero_embedding =
-                    Tensor::zeros((1, self.embedding_dim), DType::F32, &self.device)?;
-                embeddings.push(zero_embedding);
-            } else {
-                // Stack and mean pool the token embeddings
-                let stacked = Tensor::stack(&token_embeddings, 0)?;
-                let mean_embedding = stacked.mean(0)?;
-                embeddings.push(mean_embedding.unsqueeze(0)?);
-            }
+/// Sum the embedding rows of the given tokens into `pooled` using `add_row`, which receives
+/// the pooled buffer, the (truncated) row, the token id and the token weight (1.0 without
+/// `token_weights`).
+///
+/// Only the first `pooled.len()` columns of each row are read, which applies the Matryoshka
+/// truncation while pooling. Mean pooling only rescales the sum, and that scale is removed by
+/// the L2 normalization that follows, so the division is skipped.
+fn pool_tokens<T>(
+    weights: &[T],
+    row_len: usize,
+    token_ids: &[u32],
+    token_weights: Option<&[f32]>,
+    pooled: &mut [f32],
+    add_row: impl Fn(&mut [f32], &[T], usize, f32),
+) -> Result<()> {
+    let vocab_size = weights.len() / row_len;
+    for &token_id in token_ids {
+        let token_id = token_id as usize;
+        if token_id >= vocab_size {
+            return Err(EmbedderError::TokenizationError(format!(
+                "Token id {} is out of range for a vocabulary of {} tokens",
+                token_id, vocab_size
+            ))
+            .into());
         }
+        let token_weight = token_weights
+            .and_then(|token_weights| token_weights.get(token_id))
+            .copied()
+            .unwrap_or(1.0);
+        let offset = token_id * row_len;
+        add_row(
+            pooled,
+            &weights[offset..offset + pooled.len()],
+            token_id,
+            token_weight,
+        );
+    }
+    Ok(())
+}
+
+/// Load the whole embedding table of a model as an f32 tensor, e.g. to train it
+pub(crate) fn load_weights_tensor(weights_path: &Path) -> Result<(Tensor, ModelFormat)> {
+    let data = fs::read(weights_path)?;
+    let tensors = SafeTensors::deserialize(&data)?;
+    let format = ModelFormat::detect(&tensors)?;
+    let view = tensors.tensor(format.weights_tensor())?;
+    let weights = candle::safetensors::convert(&view, &Device::Cpu)?.to_dtype(DType::F32)?;
+    Ok((weights, format))
+}
+
+/// Normalize a vector in place using L2 normalization.
+/// Zero and non-finite vectors are left as they are, to be caught by the indexer.
+pub(crate) fn normalize_l2(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return;
+    }
+    for x in v.iter_mut() {
+        *x /= norm;
+    }
+}
+
+impl Embedder for StaticEmbedder {
+    fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    /// Embed a batch of strings and return as ndarray
+    fn embed_batch(&self, texts: &[&str]) -> Result<Array2<f32>> {
+        let embeddings = self.embed_batch_flat(texts)?;
+        Ok(Array2::from_shape_vec(
+            (texts.len(), self.embedding_dim),
+            embeddings,
+        )?)
+    }
 
-        // Stack all sentence embeddings
-        let result = Tensor::cat(&embeddings, 0)?;
+    /// Embed a batch of strings and return as Vec<Vec<f32>>
+    fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let embeddings = self.embed_batch_flat(texts)?;
+        Ok(embeddings
+            .chunks_exact(self.embedding_dim)
+            .map(|embedding| embedding.to_vec())
+            .collect())
+    }
 
-        // Normalize embeddings
-        let result = self.normalize_l2(&result)?;
+    fn fit_token_weights(&self, texts: &[&str], weighting: TokenWeighting) -> Result<TokenWeights> {
+        let mut counts = TokenCounts::new(self.embedding_weights.vocab_size(self.model_dim));
+        for chunk in texts.chunks(FIT_CHUNK_SIZE) {
+            for encoding in self.encode(chunk)? {
+                counts.add(encoding.get_ids());
+            }
+        }
+        Ok(counts.finish(weighting))
+    }
 
-        Ok(result)
+    fn embed_batch_vec_weighted(
+        &self,
+        texts: &[&str],
+        weights: &TokenWeights,
+    ) -> Result<Vec<Vec<f32>>> {
+        let embeddings = self.embed_batch_flat_weighted(texts, Some(weights))?;
+        Ok(embeddings
+            .chunks_exact(self.embedding_dim)
+            .map(|embedding| embedding.to_vec())
+            .collect())
     }
 
-    /// Normalize a tensor using L2 normalization
-    fn normalize_l2(&self, v: &Tensor) -> Result<Tensor> {
-        let norm = v.sqr()?.sum_keepdim(1)?.sqrt()?;
-        Ok(v.broadcast_div(&norm)?)
+    fn embed_batch_into(
+        &self,
+        texts: &[&str],
+        weights: Option<&TokenWeights>,
+        embeddings: &mut [f32],
+    ) -> Result<()> {
+        self.pool_batch(texts, weights, embeddings)
     }
 }
 
+/// Truncate an embedding to its first `dim` dimensions and re-normalize it
+pub fn truncate_embedding(embedding: &[f32], dim: usize) -> Vec<f32> {
+    let mut truncated = embedding[..dim.min(embedding.len())].to_vec();
+    normalize_l2(&mut truncated);
+    truncated
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use anyhow::Result;
+    use std::time::Instant;
+    use tokenizers::{PaddingParams, PaddingStrategy};
 
     // Helper function for dot product (cosine similarity for normalized vectors)
     fn dot_product(a: &[f32], b: &[f32]) -> f32 {
@@ -244,11 +657,189 @@ mod tests {
         (sum_squares - 1.0).abs() < 0.01 // Allow small floating point variation
     }
 
+    #[test]
+    fn test_model_spec_resolve() {
+        // registered names resolve to a remote model
+        let spec = ModelSpec::resolve("static-similarity-mrl-multilingual-v1").unwrap();
+        assert_eq!(spec.id, "static-similarity-mrl-multilingual-v1");
+        assert!(matches!(spec.location, ModelLocation::Remote(_)));
+        assert_eq!(ModelSpec::default().id, DEFAULT_MODEL);
+
+        // unknown names that are not directories are rejected
+        assert!(ModelSpec::resolve("no-such-model").is_err());
+
+        // a local directory must contain the model files
+        let dir = tempfile::tempdir().unwrap();
+        let dir_str = dir.path().to_str().unwrap();
+        assert!(ModelSpec::resolve(dir_str).is_err());
+        for file in MODEL_FILES.iter() {
+            fs::write(dir.path().join(file), b"").unwrap();
+        }
+        let spec = ModelSpec::resolve(dir_str).unwrap();
+        assert_eq!(
+            spec.location,
+            ModelLocation::Local(dir.path().to_path_buf())
+        );
+    }
+
+    // Per-token tensor pooling (the previous implementation), kept as a reference for the kernel
+    fn reference_embed(weights: &Tensor, token_ids: &[u32]) -> Result<Vec<f32>> {
+        // a row without tokens embeds to zeros, like the pooling kernel
+        if token_ids.is_empty() {
+            return Ok(vec![0f32; weights.dim(1)?]);
+        }
+        let rows = token_ids
+            .iter()
+            .map(|&token_id| weights.get(token_id as usize))
+            .collect::<candle::Result<Vec<_>>>()?;
+        let mean = Tensor::stack(&rows, 0)?.mean(0)?;
+        let norm = mean.sqr()?.sum_all()?.sqrt()?;
+        Ok(mean.broadcast_div(&norm)?.to_vec1::<f32>()?)
+    }
+
+    #[test]
+    fn test_pool_tokens_matches_tensor_mean() -> Result<()> {
+        let (vocab_size, dim) = (6, 4);
+        let weights: Vec<f32> = (0..vocab_size * dim)
+            .map(|i| ((i * 7) % 11) as f32 - 5.0)
+            .collect();
+        let weights_tensor = Tensor::from_slice(&weights, (vocab_size, dim), &Device::Cpu)?;
+        let token_ids = [1u32, 3, 3, 5];
+
+        let expected = reference_embed(&weights_tensor, &token_ids)?;
+
+        // every precision should pool to (nearly) the same normalized embedding
+        for (precision, tolerance) in [
+            (WeightPrecision::F32, 1e-5),
+            (WeightPrecision::F16, 1e-3),
+            (WeightPrecision::Int8, 2e-2),
+        ] {
+            let table = EmbeddingWeights::Owned(weights.clone()).with_precision(precision, dim);
+            let mut pooled = vec![0f32; dim];
+            table.pool(dim, &token_ids, None, &mut pooled)?;
+            normalize_l2(&mut pooled);
+            for (actual, expected) in pooled.iter().zip(expected.iter()) {
+                assert!(
+                    (actual - expected).abs() < tolerance,
+                    "{:?} pooling differs: {} vs {}",
+                    precision,
+                    actual,
+                    expected
+                );
+            }
+
+            // a row without tokens pools to zeros in both implementations
+            let mut empty = vec![0f32; dim];
+            table.pool(dim, &[], None, &mut empty)?;
+            normalize_l2(&mut empty);
+            assert_eq!(empty, reference_embed(&weights_tensor, &[])?);
+
+            // out of vocabulary token ids are rejected
+            let out_of_range = [vocab_size as u32];
+            assert!(table.pool(dim, &out_of_range, None, &mut pooled).is_err());
+        }
+        Ok(())
+    }
+
+    /// Compares the pooling kernel with the per-token tensor implementation on the TMDB rows.
+    /// Run with `cargo test --release bench_embed_tmdb -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_embed_tmdb() -> Result<()> {
+        let embedder = StaticEmbedder::new()?;
+        let csv = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/tmdb.csv"))?;
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        let chunk_size = 500;
+
+        let start = Instant::now();
+        for chunk in rows.chunks(chunk_size) {
+            embedder.embed_batch_flat(chunk)?;
+        }
+        let kernel_elapsed = start.elapsed();
+
+        // the previous path padded every batch and filtered the padding back out
+        let embedding_weights = embedder.embedding_weights.as_f32().expect("f32 weights");
+        let vocab_size = embedding_weights.len() / embedder.model_dim;
+        let weights = Tensor::from_slice(
+            embedding_weights,
+            (vocab_size, embedder.model_dim),
+            &Device::Cpu,
+        )?;
+        let mut padded_tokenizer = embedder.tokenizer.clone();
+        padded_tokenizer.with_padding(Some(PaddingParams {
+            strategy: PaddingStrategy::BatchLongest,
+            ..Default::default()
+        }));
+        let start = Instant::now();
+        for chunk in rows.chunks(chunk_size) {
+            let encodings = padded_tokenizer
+                .encode_batch(chunk.iter().map(|s| s.to_string()).collect(), true)
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+            for encoding in &encodings {
+                let token_ids: Vec<u32> = encoding
+                    .get_ids()
+                    .iter()
+                    .zip(encoding.get_attention_mask())
+                    .filter(|(_, mask)| **mask == 1)
+                    .map(|(token_id, _)| *token_id)
+                    .collect();
+                reference_embed(&weights, &token_ids)?;
+            }
+        }
+        let reference_elapsed = start.elapsed();
+
+        println!(
+            "Embedded {} rows: kernel {:?}, per-token tensors {:?}, speedup {:.1}x",
+            rows.len(),
+            kernel_elapsed,
+            reference_elapsed,
+            reference_elapsed.as_secs_f64() / kernel_elapsed.as_secs_f64()
+        );
+        Ok(())
+    }
+
+    // Write a safetensors model file with the given embedding table
+    fn write_weights(
+        path: &Path,
+        dtype: safetensors::Dtype,
+        shape: Vec<usize>,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let view = safetensors::tensor::TensorView::new(dtype, shape, bytes)?;
+        let data = safetensors::serialize([("embedding.weight", view)], &None)?;
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_embedding_weights_memory_mapped() -> Result<()> {
+        let weights: Vec<f32> = (0..12).map(|i| i as f32 * 0.5).collect();
+        let bytes: Vec<u8> = weights.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("model.safetensors");
+        write_weights(&path, safetensors::Dtype::F32, vec![3, 4], &bytes)?;
+
+        let (loaded, format, shape) = EmbeddingWeights::load(&path)?;
+        assert_eq!(format, ModelFormat::SentenceTransformers);
+        assert_eq!(shape, vec![3, 4]);
+        assert!(matches!(loaded, EmbeddingWeights::Mapped { .. }));
+        assert_eq!(loaded.as_f32(), Some(weights.as_slice()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_embedding() {
+        let embedding = [0.6, 0.0, 0.8, 0.0];
+        let truncated = truncate_embedding(&embedding, 2);
+        assert_eq!(truncated, vec![1.0, 0.0]);
+        assert!(is_normalized(&truncate_embedding(&[0.5, 0.5, 0.5, 0.5], 3)));
+    }
+
     #[test]
     fn test_embed_batch_vec_structure() -> Result<()> {
         // 1. Instantiate Embedder
         println!("Initializing embedder for test...");
-        let embedder = Embedder::new()?;
+        let embedder = StaticEmbedder::new()?;
         let expected_dim = embedder.embedding_dim;
         println!("Embedder initialized. Expected dimension: {}", expected_dim);
 
@@ -295,7 +886,7 @@ mod tests {
     fn test_embed_batch_vec_semantic_similarity() -> Result<()> {
         // 1. Instantiate Embedder
         println!("Initializing embedder for test...");
-        let embedder = Embedder::new()?;
+        let embedder = StaticEmbedder::new()?;
         println!("Embedder initialized.");
 
         // 2. Define Test Input with more strongly differentiated semantically similar/dissimilar pairs
@@ -377,4 +968,45 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_reduced_precision_preserves_rankings() -> Result<()> {
+        // Same texts as the semantic similarity test
+        let texts = vec![
+            "Dogs are popular pets that need to be walked regularly.",
+            "Canines make great companions and require daily exercise.",
+            "Quantum mechanics is a fundamental theory in physics.",
+            "Physics uses quantum theory to describe subatomic particles.",
+            "Mountains are large landforms that rise prominently above their surroundings.",
+        ];
+        // For each text, the other texts ordered from most to least similar
+        let rankings = |embeddings: &[Vec<f32>]| -> Vec<Vec<usize>> {
+            (0..embeddings.len())
+                .map(|i| {
+                    let mut others: Vec<usize> =
+                        (0..embeddings.len()).filter(|j| *j != i).collect();
+                    others.sort_by(|a, b| {
+                        dot_product(&embeddings[i], &embeddings[*b])
+                            .total_cmp(&dot_product(&embeddings[i], &embeddings[*a]))
+                    });
+                    others
+                })
+                .collect()
+        };
+
+        let expected = rankings(&StaticEmbedder::new()?.embed_batch_vec(&texts)?);
+        for precision in [WeightPrecision::F16, WeightPrecision::Int8] {
+            let embedder = StaticEmbedder::new()?.with_precision(precision);
+            let embeddings = embedder.embed_batch_vec(&texts)?;
+            assert!(embeddings.iter().all(|e| is_normalized(e)));
+            assert_eq!(
+                rankings(&embeddings),
+                expected,
+                "{:?} weights changed the similarity rankings",
+                precision
+            );
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use safetensors::SafeTensors;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::embedding::embedder::Embedder;
+
+const WEIGHTS_TENSOR: &str = "token_weights";
+
+// Smoothing parameter of the SIF weights a / (a + p(w))
+const SIF_SMOOTHING: f32 = 1e-3;
+
+/// How token embeddings are weighted when pooled into a sentence embedding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TokenWeighting {
+    /// Uniform mean over the tokens
+    #[default]
+    Mean,
+    /// Smooth inverse frequency, `a / (a + p(w))` with token frequencies fitted on the table
+    Sif,
+    /// Inverse document frequency fitted on the table
+    Idf,
+}
+
+impl TokenWeighting {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenWeighting::Mean => "mean",
+            TokenWeighting::Sif => "sif",
+            TokenWeighting::Idf => "idf",
+        }
+    }
+}
+
+impl FromStr for TokenWeighting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mean" => Ok(TokenWeighting::Mean),
+            "sif" => Ok(TokenWeighting::Sif),
+            "idf" => Ok(TokenWeighting::Idf),
+            other => Err(anyhow::anyhow!(
+                "Unknown token weighting '{}': expected 'mean', 'sif' or 'idf'",
+                other
+            )),
+        }
+    }
+}
+
+/// Token statistics collected over a corpus, one document per row
+pub struct TokenCounts {
+    term_counts: Vec<u64>,
+    doc_counts: Vec<u64>,
+    num_docs: u64,
+    num_tokens: u64,
+}
+
+impl TokenCounts {
+    pub fn new(vocab_size: usize) -> Self {
+        Self {
+            term_counts: vec![0; vocab_size],
+            doc_counts: vec![0; vocab_size],
+            num_docs: 0,
+            num_tokens: 0,
+        }
+    }
+
+    /// Count the tokens of one document
+    pub fn add(&mut self, token_ids: &[u32]) {
+        self.num_docs += 1;
+        self.num_tokens += token_ids.len() as u64;
+        for &token_id in token_ids {
+            if let Some(count) = self.term_counts.get_mut(token_id as usize) {
+                *count += 1;
+            }
+        }
+        let mut unique = token_ids.to_vec();
+        unique.sort_unstable();
+        unique.dedup();
+        for token_id in unique {
+            if let Some(count) = self.doc_counts.get_mut(token_id as usize) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// Compute the per-token weights. Tokens never seen in the corpus get the highest weight,
+    /// so that query terms missing from the table still count.
+    pub fn finish(self, weighting: TokenWeighting) -> TokenWeights {
+        let weights = match weighting {
+            TokenWeighting::Mean => vec![1.0; self.term_counts.len()],
+            TokenWeighting::Sif => {
+                let num_tokens = self.num_tokens.max(1) as f32;
+                self.term_counts
+                    .iter()
+                    .map(|count| SIF_SMOOTHING / (SIF_SMOOTHING + *count as f32 / num_tokens))
+                    .collect()
+            }
+            TokenWeighting::Idf => {
+                let num_docs = self.num_docs as f32;
+                self.doc_counts
+                    .iter()
+                    .map(|count| ((num_docs + 1.0) / (*count as f32 + 1.0)).ln() + 1.0)
+                    .collect()
+            }
+        };
+        TokenWeights::new(weighting, weights)
+    }
+}
+
+/// Per-token pooling weights fitted on the rows of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenWeights {
+    pub weighting: TokenWeighting,
+    weights: Vec<f32>,
+    fingerprint: u64,
+}
+
+impl TokenWeights {
+    fn new(weighting: TokenWeighting, weights: Vec<f32>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        weighting.hash(&mut hasher);
+        for weight in &weights {
+            weight.to_bits().hash(&mut hasher);
+        }
+        Self {
+            weighting,
+            weights,
+            fingerprint: hasher.finish(),
+        }
+    }
+
+    /// The dense weight vector indexed by token id
+    pub fn as_slice(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Hash of the weights, distinguishing the weights fitted on different tables
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// Save the weights as a safetensors file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes: Vec<u8> = self.weights.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let view = safetensors::tensor::TensorView::new(
+            safetensors::Dtype::F32,
+            vec![self.weights.len()],
+            &bytes,
+        )?;
+        safetensors::serialize_to_file([(WEIGHTS_TENSOR, view)], &None, path)?;
+        Ok(())
+    }
+
+    /// Load weights saved with [`TokenWeights::save`]
+    pub fn load(path: &Path, weighting: TokenWeighting) -> Result<Self> {
+        let data = fs::read(path)?;
+        let tensors = SafeTensors::deserialize(&data)?;
+        let weights = tensors
+            .tensor(WEIGHTS_TENSOR)?
+            .data()
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+        Ok(Self::new(weighting, weights))
+    }
+}
+
+/// Embeds with the token weights fitted on a table, so that indexing and querying
+/// that table pool tokens the same way
+pub struct WeightedEmbedder {
+    inner: Arc<dyn Embedder>,
+    weights: Arc<TokenWeights>,
+}
+
+impl WeightedEmbedder {
+    pub fn new(inner: Arc<dyn Embedder>, weights: Arc<TokenWeights>) -> Self {
+        Self { inner, weights }
+    }
+}
+
+impl Embedder for WeightedEmbedder {
+    fn model_id(&self) -> &str {
+        self.inner.model_id()
+    }
+
+    fn embedding_dim(&self) -> usize {
+        self.inner.embedding_dim()
+    }
+
+    fn embed_batch_vec(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.inner.embed_batch_vec_weighted(texts, &self.weights)
+    }
+
+    fn embed_batch_into(
+        &self,
+        texts: &[&str],
+        weights: Option<&TokenWeights>,
+        embeddings: &mut [f32],
+    ) -> Result<()> {
+        let weights = weights.unwrap_or(self.weights.as_ref());
+        self.inner
+            .embed_batch_into(texts, Some(weights), embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_token_weights() {
+        let mut counts = TokenCounts::new(4);
+        counts.add(&[0, 1, 1]);
+        counts.add(&[0, 2]);
+
+        // idf: token 0 is in every row, token 3 in none
+        let idf = counts.finish(TokenWeighting::Idf);
+        let weights = idf.as_slice();
+        assert!((weights[0] - 1.0).abs() < 1e-6);
+        assert!(weights[1] > weights[0]);
+        assert_eq!(weights[1], weights[2]);
+        assert!(weights[3] > weights[1]);
+
+        // sif: frequent tokens are down-weighted
+        let mut counts = TokenCounts::new(4);
+        counts.add(&[0, 1, 1]);
+        counts.add(&[0, 2]);
+        let sif = counts.finish(TokenWeighting::Sif);
+        let weights = sif.as_slice();
+        assert_eq!(weights[0], weights[1]);
+        assert!(weights[2] > weights[1]);
+        assert_eq!(weights[3], 1.0);
+    }
+
+    #[test]
+    fn test_token_weights_save_load() -> Result<()> {
+        let mut counts = TokenCounts::new(3);
+        counts.add(&[0, 1]);
+        let weights = counts.finish(TokenWeighting::Idf);
+
+        let dir = tempfile::tempdir()?;
+        let path = dir
+            .path()
+            .join("_dfembed")
+            .join("token_weights.safetensors");
+        weights.save(&path)?;
+        assert_eq!(TokenWeights::load(&path, TokenWeighting::Idf)?, weights);
+        Ok(())
+    }
+}
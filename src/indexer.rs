@@ -1,40 +1,179 @@
-use arrow::array::RecordBatch;
-use arrow::datatypes::Schema;
+use arrow::array::{Array, RecordBatch};
+use arrow::buffer::NullBuffer;
+use arrow::datatypes::{Field, Schema};
 
 use std::sync::Arc;
 
 use crossbeam::channel;
-use crossbeam::channel::Sender;
+use lance::dataset::Dataset;
+use rayon::ThreadPool;
 use tokio::runtime::Runtime;
 use tracing::error;
 use tracing::info;
+use tracing::warn;
 
-use crate::embedding::coordinator::EmbeddingCoordinator;
-use crate::embedding::static_embeder::Embedder;
-use crate::storage::lance::LanceStore;
+use crate::arrow::utils::vectors_to_fixed_size_list;
+use crate::embedding::coordinator::{
+    EmbeddingCoordinator, InvalidVectorCounts, InvalidVectorPolicy, TextBatch, embed_in_parallel,
+};
+use crate::embedding::embedder::Embedder;
+use crate::embedding::projection::{
+    PROJECTION_SAMPLE_SIZE, ProjectedEmbedder, Projection, ProjectionKind,
+};
+use crate::embedding::token_weights::{TokenWeighting, TokenWeights, WeightedEmbedder};
+use crate::storage::lance::{LanceStore, VECTOR_COLUMN};
 use crate::storage::lance_writer;
+use crate::storage::quantization::QuantizationOptions;
+use crate::storage::vector_index::{DistanceMetric, VectorIndexOptions};
 
 pub struct Indexer {
     batches: Vec<RecordBatch>,
     schema: Arc<Schema>,
+    embedder: Arc<dyn Embedder>,
+    token_weighting: TokenWeighting,
+    invalid_vector_policy: InvalidVectorPolicy,
+    serialization: SerializationOptions,
+    projection: Option<ProjectionKind>,
+    projection_dim: Option<usize>,
+    quantization: QuantizationOptions,
+    metric: Option<DistanceMetric>,
+    full_text_columns: Option<Vec<String>>,
+    payload_columns: Vec<String>,
+    vector_index: Option<VectorIndexOptions>,
+    vector_index_threshold: usize,
+    thread_pool: Option<Arc<ThreadPool>>,
+}
+
+/// How a row is serialized into the text that gets embedded, e.g. "title is Up; year is 2009"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerializationOptions {
+    /// Columns to include, in this order; all columns when `None`
+    pub columns: Option<Vec<String>>,
+    /// Placed between a column name and its value
+    pub key_value_separator: String,
+    /// Placed between the columns of a row
+    pub field_separator: String,
+}
+
+impl Default for SerializationOptions {
+    fn default() -> Self {
+        Self {
+            columns: None,
+            key_value_separator: " is ".to_string(),
+            field_separator: "; ".to_string(),
+        }
+    }
+}
+
+/// Summary of an indexing run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexingResult {
+    /// Rows written to the table
+    pub rows_indexed: usize,
+    /// Rows whose embedding was a zero vector (e.g. empty text)
+    pub zero_vectors: usize,
+    /// Rows whose embedding contained NaN or infinite values
+    pub non_finite_vectors: usize,
+    /// Rows left out of the table because of an invalid vector
+    pub skipped_rows: usize,
+    /// Rows stored with a null vector because of an invalid vector
+    pub null_vectors: usize,
 }
 
 impl Indexer {
-    pub fn new(batches: &[RecordBatch], schema: Arc<Schema>) -> Self {
-        let _ = Embedder::new().unwrap();
+    pub fn new(batches: &[RecordBatch], schema: Arc<Schema>, embedder: Arc<dyn Embedder>) -> Self {
         Self {
             batches: batches.to_vec(),
             schema,
+            embedder,
+            token_weighting: TokenWeighting::default(),
+            invalid_vector_policy: InvalidVectorPolicy::default(),
+            serialization: SerializationOptions::default(),
+            projection: None,
+            projection_dim: None,
+            quantization: QuantizationOptions::default(),
+            metric: None,
+            full_text_columns: None,
+            payload_columns: Vec::new(),
+            vector_index: None,
+            vector_index_threshold: 0,
+            thread_pool: None,
         }
     }
 
+    /// Distance metric of a new table, used by its searches and vector index
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    /// Store these columns of the input table next to the vectors
+    pub fn with_payload_columns(mut self, columns: Vec<String>) -> Self {
+        self.payload_columns = columns;
+        self
+    }
+
+    /// Build the vector index once the table holds at least `threshold` rows, if it has none yet
+    pub fn with_vector_index(mut self, options: VectorIndexOptions, threshold: usize) -> Self {
+        self.vector_index = Some(options);
+        self.vector_index_threshold = threshold;
+        self
+    }
+
+    /// Build a full-text (inverted) index on these columns of the table once the rows are written
+    pub fn with_full_text_index(mut self, columns: Vec<String>) -> Self {
+        self.full_text_columns = Some(columns);
+        self
+    }
+
+    /// Store int8 or binary quantized vectors next to, or instead of, the f32 vectors
+    pub fn with_quantization(mut self, quantization: QuantizationOptions) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    /// Project the vectors with a PCA or whitening transform fitted on a sample of the rows,
+    /// keeping `output_dim` dimensions (all of them when `None`)
+    pub fn with_projection(mut self, kind: ProjectionKind, output_dim: Option<usize>) -> Self {
+        self.projection = Some(kind);
+        self.projection_dim = output_dim;
+        self
+    }
+
+    /// How rows are serialized into the embedded text
+    pub fn with_serialization_options(mut self, serialization: SerializationOptions) -> Self {
+        self.serialization = serialization;
+        self
+    }
+
+    /// How rows with a zero or non-finite embedding are handled
+    pub fn with_invalid_vector_policy(mut self, policy: InvalidVectorPolicy) -> Self {
+        self.invalid_vector_policy = policy;
+        self
+    }
+
+    /// Pool token embeddings with weights fitted on the indexed rows (static models only)
+    pub fn with_token_weighting(mut self, token_weighting: TokenWeighting) -> Self {
+        self.token_weighting = token_weighting;
+        self
+    }
+
+    /// Embed in memory on this pool instead of one built for the run
+    pub fn with_thread_pool(mut self, thread_pool: Arc<ThreadPool>) -> Self {
+        self.thread_pool = Some(thread_pool);
+        self
+    }
+
     /// This function orchestrates the main workflow:
-    /// 1. Transforms Arrow record batches into text chunks
+    /// 1. Transforms Arrow record batches into text chunks, fitting the token weights
+    ///    and the projection on them when configured
     /// 2. Spawns  embedding worker threads that:
     ///    - Receive text chunks from a channel
-    ///    - Generate embeddings using the static embedding model
+    ///    - Generate embeddings using the configured embedding backend
     ///    - Send results to a writer channel
     /// 3. Runs a writer thread that stores the embeddings and metadata in a Lance database
+    /// 4. Brings the existing indices up to date, then builds the requested full-text index and
+    ///    the vector index once the table passes its row threshold
     pub fn run(
         &self,
         num_workers: usize,
@@ -43,7 +182,7 @@ impl Indexer {
         database_name: &str,
         table_name: &str,
         vector_dim: usize,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<IndexingResult> {
         info!(
             "Starting indexer with {} workers and embedding chunk size {} and write buffer size {}",
             num_workers, embedding_chunk_size, write_buffer_size
@@ -52,12 +191,90 @@ impl Indexer {
         let rt = Arc::new(Runtime::new()?);
         let (send_to_embedder, receive_from_embedder) = channel::unbounded();
         let (send_to_writer, receive_from_writer) = channel::unbounded();
-        let store = LanceStore::new_with_database(database_name, table_name, vector_dim);
-        let store = Arc::new(store);
+        let store = LanceStore::new_with_database(database_name, table_name, vector_dim)
+            .with_model(self.embedder.model_id())
+            .with_token_weighting(self.token_weighting);
+        let existing = rt.block_on(store.open_dataset())?;
+        // an existing table keeps the vector columns it was created with
+        let quantization = match &existing {
+            Some(db) => {
+                let stored = store.stored_quantization(db)?;
+                if self.quantization != QuantizationOptions::default()
+                    && self.quantization != stored
+                {
+                    warn!(
+                        "Table was created with {} quantization, ignoring the requested one",
+                        stored.quantization.as_str()
+                    );
+                }
+                stored
+            }
+            None => self.quantization,
+        };
+        // and the metric it was created with
+        let metric = match &existing {
+            Some(db) => {
+                let stored = store.stored_metric(db)?;
+                if self.metric.is_some_and(|metric| metric != stored) {
+                    warn!(
+                        "Table was created with the {} metric, ignoring the requested one",
+                        stored.as_str()
+                    );
+                }
+                stored
+            }
+            None => self.metric.unwrap_or_default(),
+        };
+        let (payload_indices, payload_fields) = self.payload_fields()?;
+        if let Some(db) = &existing {
+            let stored = store.stored_payload_columns(db);
+            if stored != self.payload_columns {
+                return Err(anyhow::anyhow!(
+                    "Table stores the payload columns {:?}, but {:?} were given",
+                    stored,
+                    self.payload_columns
+                ));
+            }
+        }
+        let store = store
+            .with_quantization(quantization)
+            .with_metric(metric)
+            .with_payload(payload_fields);
+
+        // transform the batches to text chunks
+        let records = transform_batches(&self.batches, &self.schema, &self.serialization)?;
+        let stored = existing.as_ref().map(|db| (&store, db));
+        let (embedder, fitted_weights) = self.token_weighted_embedder(stored, &records)?;
+        let (embedder, projection, fitted_projection) = self.projected_embedder(
+            stored,
+            embedder,
+            &records,
+            num_workers,
+            embedding_chunk_size,
+        )?;
+        let store = match &projection {
+            Some(projection) => Arc::new(store.with_projection(projection)),
+            None => Arc::new(store),
+        };
+        // the table metadata names the weighting and the projection from its first commit,
+        // so both must be readable by then
+        if let Some(weights) = fitted_weights {
+            store.save_token_weights(&weights)?;
+        }
+        if let (Some(projection), true) = (&projection, fitted_projection) {
+            store.save_projection(projection)?;
+        }
 
-        // transform the batches to text chunks and send them to the embedder
-        if let Err(e) = transform_batches(&self.batches, &self.schema, send_to_embedder.clone()) {
-            error!("Error transforming batches: {}", e);
+        // send the text chunks to the embedder
+        for (texts, batch) in records.into_iter().zip(&self.batches) {
+            let payload = if payload_indices.is_empty() {
+                None
+            } else {
+                Some(batch.project(&payload_indices)?)
+            };
+            if let Err(e) = send_to_embedder.send(TextBatch { texts, payload }) {
+                error!("Error sending batch to embedder: {}", e);
+            }
         }
         drop(send_to_embedder);
         // start embedding thread
@@ -66,50 +283,381 @@ impl Indexer {
             receive_from_embedder,
             send_to_writer,
             embedding_chunk_size,
-        );
+            embedder,
+        )
+        .with_invalid_vector_policy(self.invalid_vector_policy);
+        let invalid_vectors = coordinator.invalid_vectors();
         coordinator.start();
-        if let Err(e) =
-            lance_writer::start_writing_thread(&store, receive_from_writer, write_buffer_size, rt)
-        {
+        let rows_indexed = lance_writer::start_writing_thread(
+            &store,
+            receive_from_writer,
+            write_buffer_size,
+            Arc::clone(&rt),
+        )
+        .unwrap_or_else(|e| {
             error!("Error starting writer thread: {}", e);
+            0
+        });
+        // the table is missing when nothing was written to a new one
+        if rows_indexed > 0 || existing.is_some() {
+            if existing.is_some() {
+                rt.block_on(store.optimize_indices())?;
+            }
+            if let Some(columns) = &self.full_text_columns {
+                info!("Building full-text index on {:?}", columns);
+                rt.block_on(store.create_full_text_index(columns))?;
+            }
+            if let Some(options) = &self.vector_index {
+                self.build_vector_index(&store, &rt, options, quantization)?;
+            }
+        }
+
+        let num_invalid = invalid_vectors.total();
+        if num_invalid > 0 {
+            warn!(
+                "{} rows produced zero or non-finite vectors ({:?} policy)",
+                num_invalid, self.invalid_vector_policy
+            );
+        }
+        let (skipped_rows, null_vectors) = match self.invalid_vector_policy {
+            InvalidVectorPolicy::Skip => (num_invalid, 0),
+            InvalidVectorPolicy::Null => (0, num_invalid),
+            InvalidVectorPolicy::Fail if num_invalid > 0 => {
+                return Err(anyhow::anyhow!(
+                    "Indexing stopped: {} rows produced zero vectors and {} non-finite vectors \
+                     ({} rows were written before stopping)",
+                    invalid_vectors.zero_norm(),
+                    invalid_vectors.non_finite(),
+                    rows_indexed
+                ));
+            }
+            InvalidVectorPolicy::Fail => (0, 0),
+        };
+
+        Ok(IndexingResult {
+            rows_indexed,
+            zero_vectors: invalid_vectors.zero_norm(),
+            non_finite_vectors: invalid_vectors.non_finite(),
+            skipped_rows,
+            null_vectors,
+        })
+    }
+
+    /// Runs the serialization and embedding stages without writing to Lance.
+    /// Returns the input batches with an extra vector column, in the original row order;
+    /// rows with a zero or non-finite embedding get a null vector.
+    /// The vectors are pooled with the token weights and projected like those of `run`: with
+    /// the ones stored with `table` when it exists, so that they can be compared with its
+    /// vectors, and otherwise with weights and a projection fitted on the batches.
+    pub fn embed_batches(
+        &self,
+        num_workers: usize,
+        embedding_chunk_size: usize,
+        table: Option<&LanceStore>,
+    ) -> anyhow::Result<(Arc<Schema>, Vec<RecordBatch>, IndexingResult)> {
+        if self.schema.column_with_name(VECTOR_COLUMN).is_some() {
+            return Err(anyhow::anyhow!(
+                "Table already has a '{}' column",
+                VECTOR_COLUMN
+            ));
+        }
+        let records = transform_batches(&self.batches, &self.schema, &self.serialization)?;
+        let existing = match table {
+            Some(store) => Runtime::new()?.block_on(store.open_dataset())?,
+            None => None,
+        };
+        let stored = table.zip(existing.as_ref());
+        let (embedder, _) = self.token_weighted_embedder(stored, &records)?;
+        let (embedder, _, _) = self.projected_embedder(
+            stored,
+            embedder,
+            &records,
+            num_workers,
+            embedding_chunk_size,
+        )?;
+        let texts: Vec<&str> = records.iter().flatten().map(|s| s.as_str()).collect();
+        let dim = embedder.embedding_dim();
+        let embeddings = embed_in_parallel(
+            embedder.as_ref(),
+            &texts,
+            embedding_chunk_size,
+            &self.thread_pool(num_workers)?,
+        )?;
+
+        let invalid_vectors = InvalidVectorCounts::default();
+        let valid: Vec<bool> = embeddings
+            .chunks_exact(dim)
+            .map(|embedding| invalid_vectors.check(embedding))
+            .collect();
+        let vectors = vectors_to_fixed_size_list(embeddings, dim, Some(NullBuffer::from(valid)))?;
+
+        let mut fields: Vec<Field> = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| field.as_ref().clone())
+            .collect();
+        fields.push(Field::new(VECTOR_COLUMN, vectors.data_type().clone(), true));
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            self.schema.metadata().clone(),
+        ));
+
+        let mut offset = 0;
+        let mut batches = Vec::with_capacity(self.batches.len());
+        for batch in &self.batches {
+            let mut columns = batch.columns().to_vec();
+            columns.push(Arc::new(vectors.slice(offset, batch.num_rows())));
+            batches.push(RecordBatch::try_new(Arc::clone(&schema), columns)?);
+            offset += batch.num_rows();
+        }
+
+        let result = IndexingResult {
+            rows_indexed: texts.len(),
+            zero_vectors: invalid_vectors.zero_norm(),
+            non_finite_vectors: invalid_vectors.non_finite(),
+            skipped_rows: 0,
+            null_vectors: invalid_vectors.total(),
+        };
+        Ok((schema, batches, result))
+    }
+
+    /// The pool given with `with_thread_pool`, or a new one of `num_threads` threads
+    fn thread_pool(&self, num_threads: usize) -> anyhow::Result<Arc<ThreadPool>> {
+        match &self.thread_pool {
+            Some(thread_pool) => Ok(Arc::clone(thread_pool)),
+            None => Ok(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()?,
+            )),
         }
+    }
+
+    /// Resolves the payload columns in the input schema, returning their indices and fields
+    fn payload_fields(&self) -> anyhow::Result<(Vec<usize>, Vec<Field>)> {
+        let mut indices = Vec::with_capacity(self.payload_columns.len());
+        let mut fields = Vec::with_capacity(self.payload_columns.len());
+        for name in &self.payload_columns {
+            if LanceStore::is_reserved_column(name) {
+                return Err(anyhow::anyhow!(
+                    "Payload column '{}' clashes with a column written by dfembed",
+                    name
+                ));
+            }
+            let index = self
+                .schema
+                .index_of(name)
+                .map_err(|_| anyhow::anyhow!("Column '{}' not found in the table", name))?;
+            indices.push(index);
+            fields.push(self.schema.field(index).clone());
+        }
+        Ok((indices, fields))
+    }
 
+    /// Builds the vector index once the table holds enough rows, unless it already has one
+    fn build_vector_index(
+        &self,
+        store: &LanceStore,
+        rt: &Runtime,
+        options: &VectorIndexOptions,
+        quantization: QuantizationOptions,
+    ) -> anyhow::Result<()> {
+        let db = rt.block_on(Dataset::open(store.file_path()))?;
+        if rt.block_on(store.stored_vector_index(&db))?.is_some() {
+            return Ok(());
+        }
+        if !quantization.keep_full_precision {
+            warn!("Table only stores quantized vectors, not building a vector index");
+            return Ok(());
+        }
+        let num_rows = rt.block_on(store.count_rows())?;
+        if num_rows < self.vector_index_threshold.max(1) {
+            info!(
+                "Table has {} rows, the vector index is built from {}",
+                num_rows, self.vector_index_threshold
+            );
+            return Ok(());
+        }
+        info!(
+            "Building {} vector index on {} rows",
+            options.index_type.as_str(),
+            num_rows
+        );
+        rt.block_on(store.create_vector_index(options))?;
         Ok(())
     }
+
+    /// Returns the embedder to index with, pooling with the token weights of the table.
+    /// An existing table, given with its store, keeps the weights its vectors were pooled
+    /// with; otherwise the weights are fitted on the records and returned so they can be
+    /// saved with the new table.
+    fn token_weighted_embedder(
+        &self,
+        existing: Option<(&LanceStore, &Dataset)>,
+        records: &[Vec<String>],
+    ) -> anyhow::Result<(Arc<dyn Embedder>, Option<Arc<TokenWeights>>)> {
+        let stored_weights = match existing {
+            Some((store, db)) => store.stored_token_weights(db)?,
+            None => None,
+        };
+
+        let (weights, fitted) = match stored_weights {
+            Some(weights) => {
+                if weights.weighting != self.token_weighting {
+                    warn!(
+                        "Table already uses {} token weighting, ignoring {}",
+                        weights.weighting.as_str(),
+                        self.token_weighting.as_str()
+                    );
+                }
+                (weights, false)
+            }
+            None if self.token_weighting == TokenWeighting::Mean => {
+                return Ok((Arc::clone(&self.embedder), None));
+            }
+            None if existing.is_some() => {
+                return Err(anyhow::anyhow!(
+                    "Cannot use {} token weighting: the table already holds mean pooled vectors",
+                    self.token_weighting.as_str()
+                ));
+            }
+            None => {
+                info!(
+                    "Fitting {} token weights on {} batches",
+                    self.token_weighting.as_str(),
+                    records.len()
+                );
+                let texts: Vec<&str> = records.iter().flatten().map(|s| s.as_str()).collect();
+                let weights = self
+                    .embedder
+                    .fit_token_weights(&texts, self.token_weighting)?;
+                (Arc::new(weights), true)
+            }
+        };
+
+        let embedder: Arc<dyn Embedder> = Arc::new(WeightedEmbedder::new(
+            Arc::clone(&self.embedder),
+            Arc::clone(&weights),
+        ));
+        Ok((embedder, fitted.then_some(weights)))
+    }
+
+    /// Returns the embedder to index with, applying the projection of the table.
+    /// An existing table keeps the projection its vectors were stored with; otherwise the
+    /// projection is fitted on an evenly spaced sample of the records. The returned flag
+    /// tells whether the projection was fitted and still has to be saved.
+    fn projected_embedder(
+        &self,
+        existing: Option<(&LanceStore, &Dataset)>,
+        embedder: Arc<dyn Embedder>,
+        records: &[Vec<String>],
+        num_workers: usize,
+        embedding_chunk_size: usize,
+    ) -> anyhow::Result<(Arc<dyn Embedder>, Option<Arc<Projection>>, bool)> {
+        let stored_projection = match existing {
+            Some((store, db)) => store.stored_projection(db)?,
+            None => None,
+        };
+
+        let (projection, fitted) = match (stored_projection, self.projection) {
+            (Some(projection), requested) => {
+                if requested.is_some_and(|kind| kind != projection.kind) {
+                    warn!(
+                        "Table already uses a {} projection, ignoring the requested one",
+                        projection.kind.as_str()
+                    );
+                }
+                (projection, false)
+            }
+            (None, None) => return Ok((embedder, None, false)),
+            (None, Some(kind)) if existing.is_some() => {
+                return Err(anyhow::anyhow!(
+                    "Cannot use a {} projection: the table already holds unprojected vectors",
+                    kind.as_str()
+                ));
+            }
+            (None, Some(kind)) => {
+                let texts: Vec<&str> = records.iter().flatten().map(|s| s.as_str()).collect();
+                let step = texts.len().div_ceil(PROJECTION_SAMPLE_SIZE).max(1);
+                let sample: Vec<&str> = texts.into_iter().step_by(step).collect();
+                info!(
+                    "Fitting {} projection on {} rows",
+                    kind.as_str(),
+                    sample.len()
+                );
+                let vectors = embed_in_parallel(
+                    embedder.as_ref(),
+                    &sample,
+                    embedding_chunk_size,
+                    &self.thread_pool(num_workers)?,
+                )?;
+                let projection = Projection::fit(
+                    &vectors,
+                    embedder.embedding_dim(),
+                    kind,
+                    self.projection_dim,
+                )?;
+                (Arc::new(projection), true)
+            }
+        };
+
+        let projected: Arc<dyn Embedder> =
+            Arc::new(ProjectedEmbedder::new(embedder, Arc::clone(&projection)));
+        Ok((projected, Some(projection), fitted))
+    }
 }
 
-/// read the parquet file and send the records to the embedder
+/// transform the record batches into one text record per row
 fn transform_batches(
     batches: &[RecordBatch],
     schema: &Schema,
-    send_to_embedder: Sender<Vec<String>>,
-) -> anyhow::Result<()> {
+    options: &SerializationOptions,
+) -> anyhow::Result<Vec<Vec<String>>> {
+    // Resolve the serialized columns once
+    let columns: Vec<(usize, &str)> = match &options.columns {
+        Some(names) => names
+            .iter()
+            .map(|name| {
+                schema
+                    .index_of(name)
+                    .map(|col_idx| (col_idx, name.as_str()))
+                    .map_err(|_| anyhow::anyhow!("Column '{}' not found in the table", name))
+            })
+            .collect::<anyhow::Result<_>>()?,
+        None => schema
+            .fields()
+            .iter()
+            .enumerate()
+            .map(|(col_idx, field)| (col_idx, field.name().as_str()))
+            .collect(),
+    };
+
     // Process each batch
-    for batch in batches {
-        let mut records = Vec::new();
-        // Process each record
-        for record_idx in 0..batch.num_rows() {
-            let mut record_fields = Vec::new();
+    Ok(batches
+        .iter()
+        .map(|batch| {
+            let mut records = Vec::new();
+            // Process each record
+            for record_idx in 0..batch.num_rows() {
+                let mut record_fields = Vec::new();
 
-            // Process each column using the helper function
-            for (col_idx, field) in schema.fields().iter().enumerate() {
-                let value = extract_value_from_array(batch.column(col_idx).as_ref(), record_idx);
-                record_fields.push(format!("{} is {}", field.name(), value));
+                // Process each column using the helper function
+                for (col_idx, name) in &columns {
+                    let value =
+                        extract_value_from_array(batch.column(*col_idx).as_ref(), record_idx);
+                    record_fields.push(format!("{}{}{}", name, options.key_value_separator, value));
+                }
+                let record = record_fields.join(&options.field_separator);
+                records.push(record);
             }
-            let record = record_fields.join("; ");
-            records.push(record);
-        }
-        if let Err(e) = send_to_embedder.send(records) {
-            error!("Error sending batch to embedder: {}", e);
-        }
-    }
-    // this will be dropped anyways due to scope but adding this to be explicit
-    drop(send_to_embedder);
-    Ok(())
+            records
+        })
+        .collect())
 }
 
 // Helper function to extract a string representation of a value from an Arrow array for a given row
-fn extract_value_from_array(array: &dyn arrow::array::Array, row_idx: usize) -> String {
+pub(crate) fn extract_value_from_array(array: &dyn arrow::array::Array, row_idx: usize) -> String {
     match array.data_type() {
         arrow::datatypes::DataType::Utf8 => {
             let string_array = array
@@ -157,3 +705,164 @@ fn extract_value_from_array(array: &dyn arrow::array::Array, row_idx: usize) ->
         dt => format!("[unhandled type: {}]", dt),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::vector_index::{VectorIndexInfo, VectorIndexType};
+    use arrow::array::{AsArray, Int64Array, StringArray};
+    use arrow::datatypes::DataType;
+    use std::ops::Range;
+
+    /// Embeds a text as the counts of its bytes in four buckets
+    struct BucketEmbedder;
+
+    impl Embedder for BucketEmbedder {
+        fn model_id(&self) -> &str {
+            "buckets"
+        }
+
+        fn embedding_dim(&self) -> usize {
+            4
+        }
+
+        fn embed_batch_vec(&self, texts: &[&str]) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut counts = vec![0.0; 4];
+                    for byte in text.bytes() {
+                        counts[byte as usize % 4] += 1.0;
+                    }
+                    counts
+                })
+                .collect())
+        }
+    }
+
+    fn titles(rows: Range<usize>) -> (Arc<Schema>, RecordBatch) {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "title",
+            DataType::Utf8,
+            false,
+        )]));
+        let titles: Vec<String> = rows.map(|i| format!("film {}", i * 7919)).collect();
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![Arc::new(StringArray::from(titles))],
+        )
+        .unwrap();
+        (schema, batch)
+    }
+
+    fn films() -> (Arc<Schema>, RecordBatch) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("title", DataType::Utf8, false),
+            Field::new("year", DataType::Int64, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(StringArray::from(vec!["Up", "Heat"])),
+                Arc::new(Int64Array::from(vec![2009, 1995])),
+            ],
+        )
+        .unwrap();
+        (schema, batch)
+    }
+
+    #[test]
+    fn test_transform_batches_serialization_options() -> anyhow::Result<()> {
+        let (schema, batch) = films();
+        let batches = [batch];
+
+        let records = transform_batches(&batches, &schema, &SerializationOptions::default())?;
+        assert_eq!(
+            records,
+            vec![vec![
+                "title is Up; year is 2009",
+                "title is Heat; year is 1995"
+            ]]
+        );
+
+        let options = SerializationOptions {
+            columns: Some(vec!["year".to_string(), "title".to_string()]),
+            key_value_separator: ": ".to_string(),
+            field_separator: ", ".to_string(),
+        };
+        let records = transform_batches(&batches, &schema, &options)?;
+        assert_eq!(records[0][0], "year: 2009, title: Up");
+
+        let options = SerializationOptions {
+            columns: Some(vec!["rating".to_string()]),
+            ..Default::default()
+        };
+        assert!(transform_batches(&batches, &schema, &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_index_threshold() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let database = dir.path().to_string_lossy().to_string();
+        let options = VectorIndexOptions {
+            index_type: VectorIndexType::IvfHnswSq,
+            num_partitions: Some(2),
+            ..Default::default()
+        };
+        // index a range of rows, returning the vector index of the table afterwards
+        let index = |rows: Range<usize>| -> anyhow::Result<Option<VectorIndexInfo>> {
+            let (schema, batch) = titles(rows);
+            Indexer::new(&[batch], schema, Arc::new(BucketEmbedder))
+                .with_vector_index(options.clone(), 50)
+                .run(2, 16, 16, &database, "films", 4)?;
+            let store = LanceStore::new_with_database(&database, "films", 4);
+            let rt = Runtime::new()?;
+            let db = rt.block_on(Dataset::open(store.file_path()))?;
+            rt.block_on(store.stored_vector_index(&db))
+        };
+
+        assert_eq!(index(0..30)?, None);
+        let built = index(30..60)?.expect("the index is built once the table has 50 rows");
+        assert_eq!(built.index_type, VectorIndexType::IvfHnswSq);
+        assert_eq!(built.metric, DistanceMetric::L2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_embed_batches_projection() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let database = dir.path().to_string_lossy().to_string();
+        let vector_dim = |batches: &[RecordBatch]| {
+            batches[0]
+                .column_by_name(VECTOR_COLUMN)
+                .and_then(|column| column.as_fixed_size_list_opt())
+                .map(|vectors| vectors.value_length())
+        };
+
+        // without a table the projection is fitted on the batches
+        let (schema, batch) = titles(0..20);
+        let (_, batches, _) = Indexer::new(
+            &[batch.clone()],
+            Arc::clone(&schema),
+            Arc::new(BucketEmbedder),
+        )
+        .with_projection(ProjectionKind::Pca, Some(2))
+        .embed_batches(2, 8, None)?;
+        assert_eq!(vector_dim(&batches), Some(2));
+
+        // an indexed table lends its projection
+        Indexer::new(
+            &[batch.clone()],
+            Arc::clone(&schema),
+            Arc::new(BucketEmbedder),
+        )
+        .with_projection(ProjectionKind::Pca, Some(2))
+        .run(2, 8, 8, &database, "films", 4)?;
+        let store = LanceStore::new_with_database(&database, "films", 4);
+        let (_, batches, _) = Indexer::new(&[batch], schema, Arc::new(BucketEmbedder))
+            .embed_batches(2, 8, Some(&store))?;
+        assert_eq!(vector_dim(&batches), Some(2));
+        Ok(())
+    }
+}
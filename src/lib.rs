@@ -1,24 +1,44 @@
-use embedding::static_embeder::Embedder;
+use ::arrow::array::AsArray;
+use ::arrow::record_batch::RecordBatch;
+use embedding::coordinator::{InvalidVectorPolicy, embed_in_parallel};
+use embedding::embedder::{Backend, Embedder, EmbedderConfig};
+use embedding::fine_tune::{FineTuneConfig, FineTuner, column_pairs};
+use embedding::projection::ProjectionKind;
+use embedding::query_cache::{CachedEmbedder, DEFAULT_QUERY_CACHE_SIZE};
+use embedding::static_embeder::{DEFAULT_MODEL, ModelSpec, WeightPrecision};
+use embedding::token_weights::TokenWeighting;
+use ndarray::Array2;
+use numpy::PyArray2;
 use once_cell::sync::Lazy;
 use pyo3::Bound;
 use pyo3::prelude::*;
 use pyo3::types::PyAny;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
 use tracing::debug;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::sync::Once;
-use std::time::Instant;
-use storage::lance::LanceStore;
+use std::time::{Duration, Instant};
+use storage::dataset_cache::{Consistency, DatasetCache};
+use storage::fusion::FusionMethod;
+use storage::lance::{LanceStore, SearchOptions, TEXT_COLUMN};
+use storage::quantization::{QuantizationOptions, VectorQuantization};
+use storage::scalar_index::ScalarIndexKind;
+use storage::vector_index::{DistanceMetric, VectorIndexOptions, VectorIndexType};
 use tokio::runtime::Runtime;
 
 mod arrow;
-use arrow::utils::{convert_py_to_arrow_table, print_schema};
+use arrow::utils::{
+    TextInput, convert_py_to_arrow_table, print_schema, vectors_to_fixed_size_list,
+};
 mod embedding;
 mod storage;
 
 use tracing::error;
 use tracing::info;
 mod indexer;
-use indexer::Indexer;
+use indexer::{Indexer, IndexingResult, SerializationOptions};
 
 // Static Once variable to ensure initialization happens only once
 static INIT: Once = Once::new();
@@ -45,7 +65,12 @@ struct DfEmbedderRust {
     write_buffer_size: usize,
     database_path: PathBuf,
     vector_dim: usize,
-    embedder: Embedder,
+    embedder: Arc<dyn Embedder>,
+    embedder_config: EmbedderConfig,
+    query_cache: Option<Arc<CachedEmbedder>>,
+    datasets: Arc<DatasetCache>,
+    // shared by the in-memory embedding and query batches of every call
+    thread_pool: Arc<rayon::ThreadPool>,
 }
 
 #[pymethods]
@@ -56,19 +81,80 @@ impl DfEmbedderRust {
         embedding_chunk_size,
         write_buffer_size,
         database_name,
-        vector_dim
+        vector_dim = None,
+        model_name = DEFAULT_MODEL.to_string(),
+        backend = "static".to_string(),
+        weight_precision = "f32".to_string(),
+        query_cache_size = DEFAULT_QUERY_CACHE_SIZE,
+        model_base_url = None,
+        consistency = "latest".to_string(),
+        consistency_interval = None
     ))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         num_threads: usize,
         embedding_chunk_size: usize,
         write_buffer_size: usize,
         database_name: String,
-        vector_dim: usize,
+        vector_dim: Option<usize>,
+        model_name: String,
+        backend: String,
+        weight_precision: String,
+        query_cache_size: usize,
+        model_base_url: Option<String>,
+        consistency: String,
+        consistency_interval: Option<f64>,
     ) -> PyResult<Self> {
         init_tracing();
-        info!("Initializing Embedder");
-        let embedder = Embedder::new().expect("Failed to create real Embedder");
-        info!("Embedder initialized");
+        info!(
+            "Initializing {} Embedder with model {}",
+            backend, model_name
+        );
+        let backend = backend
+            .parse::<Backend>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let precision = weight_precision
+            .parse::<WeightPrecision>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let consistency_interval = consistency_interval
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Invalid consistency_interval: {}",
+                    e
+                ))
+            })?;
+        let consistency = Consistency::new(&consistency, consistency_interval)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        // a smaller vector_dim truncates the embeddings, otherwise the model dimension is used
+        let embedder_config = EmbedderConfig {
+            backend,
+            model: model_name,
+            output_dim: vector_dim,
+            precision,
+            base_url: model_base_url,
+        };
+        let embedder = embedder_config.load().map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Failed to create Embedder: {}",
+                e
+            ))
+        })?;
+        let vector_dim = embedder.embedding_dim();
+        info!("Embedder initialized with {} dimensions", vector_dim);
+        // a cache size of 0 disables the query cache
+        let query_cache = NonZeroUsize::new(query_cache_size)
+            .map(|capacity| Arc::new(CachedEmbedder::new(Arc::clone(&embedder), capacity)));
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Failed to create thread pool: {}",
+                    e
+                ))
+            })?;
         Ok(DfEmbedderRust {
             num_threads,
             embedding_chunk_size,
@@ -76,6 +162,10 @@ impl DfEmbedderRust {
             database_path: PathBuf::from(database_name),
             vector_dim,
             embedder,
+            embedder_config,
+            query_cache,
+            datasets: Arc::new(DatasetCache::new(consistency)),
+            thread_pool: Arc::new(thread_pool),
         })
     }
 
@@ -96,8 +186,72 @@ impl DfEmbedderRust {
     }
 
     /// Indexes an Arrow table using the configuration stored in the DfEmbedderRust instance.
-    fn index_table(&self, py_arrow_table: &Bound<'_, PyAny>, table_name: &str) -> PyResult<()> {
+    /// Returns the number of indexed rows and of rows affected by invalid vectors.
+    /// With the "fail" invalid vector policy the error is raised after the writer stops, so
+    /// the table is left partially written with the batches committed before it.
+    #[pyo3(signature = (
+        py_arrow_table,
+        table_name,
+        token_weighting = "mean".to_string(),
+        invalid_vector_policy = "skip".to_string(),
+        projection = None,
+        projection_dim = None,
+        quantization = "none".to_string(),
+        keep_full_precision = true,
+        full_text_columns = None,
+        payload_columns = None,
+        vector_index_threshold = None,
+        vector_index_type = "IVF_PQ".to_string(),
+        metric = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn index_table(
+        &self,
+        py_arrow_table: &Bound<'_, PyAny>,
+        table_name: &str,
+        token_weighting: String,
+        invalid_vector_policy: String,
+        projection: Option<String>,
+        projection_dim: Option<usize>,
+        quantization: String,
+        keep_full_precision: bool,
+        full_text_columns: Option<Vec<String>>,
+        payload_columns: Option<Vec<String>>,
+        vector_index_threshold: Option<usize>,
+        vector_index_type: String,
+        metric: Option<String>,
+    ) -> PyResult<HashMap<String, usize>> {
         debug!("Indexing Arrow table via DfEmbedderRust");
+        let token_weighting = token_weighting
+            .parse::<TokenWeighting>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let invalid_vector_policy = invalid_vector_policy
+            .parse::<InvalidVectorPolicy>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let projection = projection
+            .map(|kind| kind.parse::<ProjectionKind>())
+            .transpose()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let quantization = quantization
+            .parse::<VectorQuantization>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        if quantization == VectorQuantization::None && !keep_full_precision {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "keep_full_precision=False requires a quantization",
+            ));
+        }
+        let vector_index = VectorIndexOptions {
+            index_type: vector_index_type
+                .parse::<VectorIndexType>()
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?,
+            ..Default::default()
+        };
+        let metric = parse_metric(metric)?;
+        if projection.is_none() && projection_dim.is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "projection_dim requires a projection",
+            ));
+        }
         let py_table = convert_py_to_arrow_table(py_arrow_table)?;
         let ts = Instant::now();
         debug!("Getting record batches");
@@ -106,11 +260,33 @@ impl DfEmbedderRust {
 
         if record_batches.is_empty() {
             error!("Arrow Table contains no batches.");
-            return Ok(());
+            return Ok(indexing_result_to_dict(&IndexingResult::default()));
         }
 
         let schema = record_batches[0].schema();
-        let indexer = Indexer::new(record_batches, schema);
+        let mut indexer = Indexer::new(record_batches, schema, Arc::clone(&self.embedder))
+            .with_thread_pool(Arc::clone(&self.thread_pool))
+            .with_token_weighting(token_weighting)
+            .with_invalid_vector_policy(invalid_vector_policy)
+            .with_quantization(QuantizationOptions {
+                quantization,
+                keep_full_precision,
+            });
+        if let Some(kind) = projection {
+            indexer = indexer.with_projection(kind, projection_dim);
+        }
+        if let Some(columns) = full_text_columns {
+            indexer = indexer.with_full_text_index(columns);
+        }
+        if let Some(columns) = payload_columns {
+            indexer = indexer.with_payload_columns(columns);
+        }
+        if let Some(metric) = metric {
+            indexer = indexer.with_metric(metric);
+        }
+        if let Some(threshold) = vector_index_threshold {
+            indexer = indexer.with_vector_index(vector_index, threshold);
+        }
 
         let result = indexer.run(
             self.num_threads,
@@ -120,42 +296,654 @@ impl DfEmbedderRust {
             table_name,
             self.vector_dim,
         );
-        if let Err(e) = result {
-            error!("Error indexing arrow table: {}", e);
-            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
-                "Error indexing arrow table: {}",
+        self.datasets
+            .written(self.table_store(table_name).file_path());
+        match result {
+            Ok(result) => Ok(indexing_result_to_dict(&result)),
+            Err(e) => {
+                error!("Error indexing arrow table: {}", e);
+                Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Error indexing arrow table: {}",
+                    e
+                )))
+            }
+        }
+    }
+
+    /// Embeds an Arrow table in memory, without writing it to Lance.
+    /// Returns the table with an extra vector column, in the original row order.
+    /// The token weighting and projection apply as in index_table; an existing table_name
+    /// lends its stored weights and projection so that the vectors match its own.
+    #[pyo3(signature = (
+        py_arrow_table,
+        columns = None,
+        key_value_separator = " is ".to_string(),
+        field_separator = "; ".to_string(),
+        table_name = None,
+        token_weighting = "mean".to_string(),
+        projection = None,
+        projection_dim = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn embed_table(
+        &self,
+        py: Python<'_>,
+        py_arrow_table: &Bound<'_, PyAny>,
+        columns: Option<Vec<String>>,
+        key_value_separator: String,
+        field_separator: String,
+        table_name: Option<String>,
+        token_weighting: String,
+        projection: Option<String>,
+        projection_dim: Option<usize>,
+    ) -> PyResult<PyObject> {
+        debug!("Embedding Arrow table via DfEmbedderRust");
+        let token_weighting = token_weighting
+            .parse::<TokenWeighting>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let projection = projection
+            .map(|kind| kind.parse::<ProjectionKind>())
+            .transpose()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        if projection.is_none() && projection_dim.is_some() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "projection_dim requires a projection",
+            ));
+        }
+        let py_table = convert_py_to_arrow_table(py_arrow_table)?;
+        let serialization = SerializationOptions {
+            columns,
+            key_value_separator,
+            field_separator,
+        };
+        let indexer = Indexer::new(
+            py_table.batches(),
+            py_table.schema().clone(),
+            Arc::clone(&self.embedder),
+        )
+        .with_thread_pool(Arc::clone(&self.thread_pool))
+        .with_serialization_options(serialization)
+        .with_token_weighting(token_weighting);
+        let indexer = match projection {
+            Some(kind) => indexer.with_projection(kind, projection_dim),
+            None => indexer,
+        };
+        let table = table_name.map(|table_name| self.table_store(&table_name));
+
+        let (num_threads, chunk_size) = (self.num_threads, self.embedding_chunk_size);
+        let (schema, batches, result) = py
+            .allow_threads(|| indexer.embed_batches(num_threads, chunk_size, table.as_ref()))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Error embedding arrow table: {}",
+                    e
+                ))
+            })?;
+        if result.null_vectors > 0 {
+            info!(
+                "{} rows produced zero or non-finite vectors and were given a null vector",
+                result.null_vectors
+            );
+        }
+        let table = pyo3_arrow::PyTable::try_new(batches, schema)?;
+        Ok(table.into_pyobject(py)?.into_any().unbind())
+    }
+
+    /// Finds similar items to a query vector in the specified table.
+    /// Blocks until the search completes and returns a list of texts, or with output "records"
+    /// or "arrow" a table of the rows with their `_rowid`, `_distance` and the requested
+    /// columns. In hybrid mode the rows carry the fused and per-retriever scores instead of
+    /// `_distance`.
+    /// Query embeddings are served from the query cache unless use_cache is false.
+    /// Quantized tables rescore their candidates with the f32 vectors unless rescore is false.
+    /// A SQL filter on the stored columns is applied before the search unless prefilter is false.
+    /// nprobes, refine_factor and ef tune the vector index search, use_index=false skips it.
+    #[pyo3(signature = (
+        query,
+        table_name,
+        k,
+        use_cache = true,
+        rescore = true,
+        mode = "vector".to_string(),
+        fusion = "rrf".to_string(),
+        vector_weight = 0.5,
+        filter = None,
+        prefilter = true,
+        output = "text".to_string(),
+        columns = None,
+        metric = None,
+        nprobes = None,
+        refine_factor = None,
+        ef = None,
+        use_index = true
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn find_similar(
+        &self,
+        py: Python<'_>,
+        query: String,
+        table_name: String,
+        k: usize,
+        use_cache: bool,
+        rescore: bool,
+        mode: String,
+        fusion: String,
+        vector_weight: f32,
+        filter: Option<String>,
+        prefilter: bool,
+        output: String,
+        columns: Option<Vec<String>>,
+        metric: Option<String>,
+        nprobes: Option<usize>,
+        refine_factor: Option<u32>,
+        ef: Option<usize>,
+        use_index: bool,
+    ) -> PyResult<PyObject> {
+        let rows_output = parse_rows_output(&output)?;
+        let embedder_ref: &dyn Embedder = match &self.query_cache {
+            Some(cache) if use_cache => cache.as_ref(),
+            _ => self.embedder.as_ref(),
+        };
+
+        let options = SearchOptions {
+            rescore,
+            filter,
+            prefilter,
+            metric: parse_metric(metric)?,
+            nprobes,
+            refine_factor,
+            ef,
+            use_index,
+        };
+        let fusion = FusionMethod::for_mode(&mode, &fusion, vector_weight)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let vector_store = self.table_store(&table_name);
+        let to_py_err = |e: anyhow::Error| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                "Error finding similar items: {}",
                 e
-            )));
+            ))
+        };
+
+        match fusion {
+            Some(fusion) if rows_output => {
+                let search = vector_store.hybrid_search_rows(
+                    &query,
+                    k,
+                    embedder_ref,
+                    fusion,
+                    &options,
+                    columns.as_deref(),
+                );
+                let batch = RUNTIME.block_on(search).map_err(to_py_err)?;
+                rows_to_py(py, batch, true)
+            }
+            Some(fusion) => {
+                let search = vector_store.hybrid_search(&query, k, embedder_ref, fusion, &options);
+                let hits = RUNTIME.block_on(search).map_err(to_py_err)?;
+                let texts: Vec<String> = hits.into_iter().map(|hit| hit.text).collect();
+                Ok(texts.into_pyobject(py)?.into_any().unbind())
+            }
+            None if rows_output => {
+                let search =
+                    vector_store.search_rows(&query, k, embedder_ref, &options, columns.as_deref());
+                let batch = RUNTIME.block_on(search).map_err(to_py_err)?;
+                rows_to_py(py, batch, true)
+            }
+            None => {
+                let search = vector_store.find_most_similar(&query, k, embedder_ref, &options);
+                let texts = RUNTIME.block_on(search).map_err(to_py_err)?;
+                Ok(texts.into_pyobject(py)?.into_any().unbind())
+            }
         }
+    }
+
+    /// Searches the table for each query of a list of strings or an Arrow string array, with the
+    /// GIL released. The queries are embedded together in parallel, bypassing the query cache,
+    /// and searched concurrently. Returns one Arrow table of the rows found for every query,
+    /// with the index of their query in a query_idx column.
+    #[pyo3(signature = (
+        queries,
+        table_name,
+        k,
+        rescore = true,
+        filter = None,
+        prefilter = true,
+        columns = None,
+        metric = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn find_similar_batch(
+        &self,
+        py: Python<'_>,
+        queries: &Bound<'_, PyAny>,
+        table_name: String,
+        k: usize,
+        rescore: bool,
+        filter: Option<String>,
+        prefilter: bool,
+        columns: Option<Vec<String>>,
+        metric: Option<String>,
+    ) -> PyResult<PyObject> {
+        let queries = TextInput::extract(queries)?;
+        let options = SearchOptions {
+            rescore,
+            filter,
+            prefilter,
+            metric: parse_metric(metric)?,
+            ..Default::default()
+        };
+        let store = self.table_store(&table_name);
+        let embedder = Arc::clone(&self.embedder);
+        let thread_pool = Arc::clone(&self.thread_pool);
+        let batch = py
+            .allow_threads(|| {
+                let queries = queries.as_strs()?;
+                RUNTIME.block_on(store.search_rows_batch(
+                    &queries,
+                    k,
+                    embedder.as_ref(),
+                    &options,
+                    columns.as_deref(),
+                    &thread_pool,
+                ))
+            })
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Error finding similar items: {}",
+                    e
+                ))
+            })?;
+        rows_to_py(py, batch, true)
+    }
+
+    /// Searches the table for the rows nearest to a vector already in the space of the stored
+    /// vectors, which must have their dimension. Returns the same outputs as find_similar.
+    #[pyo3(signature = (
+        vector,
+        table_name,
+        k,
+        rescore = true,
+        filter = None,
+        prefilter = true,
+        output = "text".to_string(),
+        columns = None,
+        metric = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn find_similar_by_vector(
+        &self,
+        py: Python<'_>,
+        vector: Vec<f32>,
+        table_name: String,
+        k: usize,
+        rescore: bool,
+        filter: Option<String>,
+        prefilter: bool,
+        output: String,
+        columns: Option<Vec<String>>,
+        metric: Option<String>,
+    ) -> PyResult<PyObject> {
+        let rows_output = parse_rows_output(&output)?;
+        let options = SearchOptions {
+            rescore,
+            filter,
+            prefilter,
+            metric: parse_metric(metric)?,
+            ..Default::default()
+        };
+        let store = self.table_store(&table_name);
+        let search = store.search_rows_by_vector(&vector, k, &options, columns.as_deref());
+        let batch = RUNTIME.block_on(search).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                "Error finding similar items: {}",
+                e
+            ))
+        })?;
+        rows_to_py(py, batch, rows_output)
+    }
+
+    /// "More like this": searches the table for the rows nearest to the stored vector of the
+    /// row with the given `_rowid`, leaving the row itself out. Returns the same outputs as
+    /// find_similar.
+    #[pyo3(signature = (
+        row_id,
+        table_name,
+        k,
+        rescore = true,
+        filter = None,
+        prefilter = true,
+        output = "text".to_string(),
+        columns = None,
+        metric = None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn find_similar_to_row(
+        &self,
+        py: Python<'_>,
+        row_id: u64,
+        table_name: String,
+        k: usize,
+        rescore: bool,
+        filter: Option<String>,
+        prefilter: bool,
+        output: String,
+        columns: Option<Vec<String>>,
+        metric: Option<String>,
+    ) -> PyResult<PyObject> {
+        let rows_output = parse_rows_output(&output)?;
+        let options = SearchOptions {
+            rescore,
+            filter,
+            prefilter,
+            metric: parse_metric(metric)?,
+            ..Default::default()
+        };
+        let store = self.table_store(&table_name);
+        let search = store.search_rows_like(row_id, k, &options, columns.as_deref());
+        let batch = RUNTIME.block_on(search).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                "Error finding similar items: {}",
+                e
+            ))
+        })?;
+        rows_to_py(py, batch, rows_output)
+    }
 
+    /// Builds an ANN index on the vector column of a table, replacing an existing one.
+    /// The index is built for the metric of the table unless another one is given, and
+    /// searches of the table then use the index and its metric.
+    #[pyo3(signature = (
+        table_name,
+        index_type = "IVF_PQ".to_string(),
+        metric = None,
+        num_partitions = None,
+        num_sub_vectors = None,
+        num_edges = 20,
+        ef_construction = 150
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn create_vector_index(
+        &self,
+        py: Python<'_>,
+        table_name: String,
+        index_type: String,
+        metric: Option<String>,
+        num_partitions: Option<usize>,
+        num_sub_vectors: Option<usize>,
+        num_edges: usize,
+        ef_construction: usize,
+    ) -> PyResult<()> {
+        let options = VectorIndexOptions {
+            index_type: index_type
+                .parse::<VectorIndexType>()
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?,
+            metric: parse_metric(metric)?,
+            num_partitions,
+            num_sub_vectors,
+            num_edges,
+            ef_construction,
+        };
+        let store = self.table_store(&table_name);
+        py.allow_threads(|| RUNTIME.block_on(store.create_vector_index(&options)))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Error building the vector index: {}",
+                    e
+                ))
+            })?;
+        self.datasets.written(store.file_path());
         Ok(())
     }
 
-    /// Finds similar items to a query vector in the specified table.
-    /// Blocks until the search completes and returns a Vec<String>.
-    fn find_similar(&self, query: String, table_name: String, k: usize) -> PyResult<Vec<String>> {
-        let db_path = self.database_path.clone();
-        let vector_dim = self.vector_dim;
-        // Assuming Embedder doesn't need cloning if find_most_similar takes &Embedder
-        // If it does need Clone, add: let embedder = self.embedder.clone();
-        let embedder_ref = &self.embedder; // Pass a reference
-
-        RUNTIME.block_on(async move {
-            let vector_store =
-                LanceStore::new_with_database(&db_path.to_string_lossy(), &table_name, vector_dim);
-            vector_store
-                .find_most_similar(&query, k, embedder_ref) // Use reference
-                .await
-                .map_err(|e| {
-                    PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
-                        "Error finding similar items: {}",
-                        e
-                    ))
-                })
+    /// Builds a btree, bitmap or label_list index on a column of a table, replacing an
+    /// existing one, so that filtered searches read the index instead of scanning the column.
+    #[pyo3(signature = (table_name, column, kind = "btree".to_string()))]
+    fn create_scalar_index(
+        &self,
+        py: Python<'_>,
+        table_name: String,
+        column: String,
+        kind: String,
+    ) -> PyResult<()> {
+        let kind = kind
+            .parse::<ScalarIndexKind>()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))?;
+        let store = self.table_store(&table_name);
+        py.allow_threads(|| RUNTIME.block_on(store.create_scalar_index(&column, kind)))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Error building the scalar index: {}",
+                    e
+                ))
+            })?;
+        self.datasets.written(store.file_path());
+        Ok(())
+    }
+
+    /// Returns the number of rows of a table and the size of its vector data.
+    fn storage_stats(&self, table_name: String) -> PyResult<HashMap<String, u64>> {
+        let store = self.table_store(&table_name);
+        let stats = RUNTIME.block_on(store.storage_stats()).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                "Error reading table stats: {}",
+                e
+            ))
+        })?;
+        let bytes_per_row = stats.full_precision_bytes_per_row + stats.quantized_bytes_per_row;
+        Ok(HashMap::from([
+            ("rows".to_string(), stats.rows as u64),
+            ("vector_dim".to_string(), stats.vector_dim as u64),
+            (
+                "full_precision_bytes_per_row".to_string(),
+                stats.full_precision_bytes_per_row as u64,
+            ),
+            (
+                "quantized_bytes_per_row".to_string(),
+                stats.quantized_bytes_per_row as u64,
+            ),
+            (
+                "vector_bytes".to_string(),
+                (bytes_per_row * stats.rows) as u64,
+            ),
+            ("disk_bytes".to_string(), stats.disk_bytes),
+        ]))
+    }
+
+    /// Measures the recall@k of the quantized search of a table against the exact search,
+    /// with and without rescoring, over the given queries.
+    #[pyo3(signature = (table_name, queries, k = 10))]
+    fn quantization_recall(
+        &self,
+        table_name: String,
+        queries: Vec<String>,
+        k: usize,
+    ) -> PyResult<HashMap<String, f64>> {
+        let store = self.table_store(&table_name);
+        let queries: Vec<&str> = queries.iter().map(|q| q.as_str()).collect();
+        let report = RUNTIME
+            .block_on(store.quantization_recall(&queries, k, self.embedder.as_ref()))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Error measuring recall: {}",
+                    e
+                ))
+            })?;
+        Ok(HashMap::from([
+            ("queries".to_string(), report.queries as f64),
+            ("k".to_string(), report.k as f64),
+            ("recall".to_string(), report.recall),
+            ("rescored_recall".to_string(), report.rescored_recall),
+        ]))
+    }
+
+    /// Returns the query cache counters: hits, misses, size and capacity.
+    fn query_cache_stats(&self) -> HashMap<String, u64> {
+        let mut stats = HashMap::new();
+        if let Some(cache) = &self.query_cache {
+            let cache_stats = cache.stats();
+            stats.insert("hits".to_string(), cache_stats.hits);
+            stats.insert("misses".to_string(), cache_stats.misses);
+            stats.insert("size".to_string(), cache_stats.len as u64);
+            stats.insert("capacity".to_string(), cache_stats.capacity as u64);
+        } else {
+            for key in ["hits", "misses", "size", "capacity"] {
+                stats.insert(key.to_string(), 0);
+            }
+        }
+        stats
+    }
+
+    /// Empties the query cache and resets its counters.
+    fn clear_query_cache(&self) {
+        if let Some(cache) = &self.query_cache {
+            cache.clear();
+        }
+    }
+
+    /// Pins the cached handle of a table to a version, by default its latest one, so that
+    /// searches read that version until the table is refreshed. Returns the pinned version.
+    #[pyo3(signature = (table_name, version = None))]
+    fn pin_table_version(&self, table_name: String, version: Option<u64>) -> PyResult<u64> {
+        let store = self.table_store(&table_name);
+        RUNTIME
+            .block_on(self.datasets.pin(store.file_path(), version))
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Error pinning table version: {}",
+                    e
+                ))
+            })
+    }
+
+    /// Drops the cached handle of a table, pinned or not, so that the next search opens its
+    /// latest version.
+    fn refresh_table(&self, table_name: String) {
+        self.datasets
+            .refresh(self.table_store(&table_name).file_path());
+    }
+
+    /// Fine-tunes the static embedding table on (anchor, positive) pairs, given directly or taken
+    /// from two columns of an Arrow table, and saves the adapted model to output_dir.
+    /// Returns the mean loss of each epoch.
+    #[pyo3(signature = (
+        output_dir,
+        pairs = None,
+        py_arrow_table = None,
+        columns = None,
+        epochs = 1,
+        learning_rate = 1e-3,
+        batch_size = 64,
+        temperature = 0.05
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn fine_tune(
+        &self,
+        py: Python<'_>,
+        output_dir: String,
+        pairs: Option<Vec<(String, String)>>,
+        py_arrow_table: Option<&Bound<'_, PyAny>>,
+        columns: Option<(String, String)>,
+        epochs: usize,
+        learning_rate: f64,
+        batch_size: usize,
+        temperature: f64,
+    ) -> PyResult<Vec<f32>> {
+        let to_value_error = |e: anyhow::Error| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Error fine-tuning: {}", e))
+        };
+        if self.embedder_config.backend != Backend::Static {
+            return Err(to_value_error(anyhow::anyhow!(
+                "only static models can be fine-tuned"
+            )));
+        }
+        let pairs = match (pairs, py_arrow_table, columns) {
+            (Some(pairs), None, None) => pairs,
+            (None, Some(py_arrow_table), Some((anchor, positive))) => {
+                let py_table = convert_py_to_arrow_table(py_arrow_table)?;
+                column_pairs(py_table.batches(), py_table.schema(), &anchor, &positive)
+                    .map_err(to_value_error)?
+            }
+            _ => {
+                return Err(to_value_error(anyhow::anyhow!(
+                    "pass either pairs, or a table with the two columns to pair"
+                )));
+            }
+        };
+        let config = FineTuneConfig {
+            epochs,
+            learning_rate,
+            batch_size,
+            temperature,
+            ..Default::default()
+        };
+
+        let mut spec = ModelSpec::resolve(&self.embedder_config.model).map_err(to_value_error)?;
+        if let Some(base_url) = &self.embedder_config.base_url {
+            spec = spec.with_base_url(base_url);
+        }
+        info!(
+            "Fine-tuning {} on {} pairs into {}",
+            spec.id,
+            pairs.len(),
+            output_dir
+        );
+        py.allow_threads(|| {
+            let fine_tuner = FineTuner::from_spec(&spec)?;
+            let losses = fine_tuner.train(&pairs, &config)?;
+            fine_tuner.save(std::path::Path::new(&output_dir))?;
+            Ok(losses)
         })
+        .map_err(to_value_error)
+    }
+
+    /// Embeds a list of strings or an Arrow string array in parallel, with the GIL released.
+    /// Returns a float32 NumPy array of shape (len(texts), vector_dim), or an Arrow
+    /// FixedSizeList<Float32> array when output is "arrow", both built on the embedding buffer.
+    #[pyo3(signature = (texts, output = "numpy".to_string()))]
+    fn embed_batch(
+        &self,
+        py: Python<'_>,
+        texts: &Bound<'_, PyAny>,
+        output: String,
+    ) -> PyResult<PyObject> {
+        if output != "numpy" && output != "arrow" {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unknown output '{}': expected 'numpy' or 'arrow'",
+                output
+            )));
+        }
+        let texts = TextInput::extract(texts)?;
+        let embedder = Arc::clone(&self.embedder);
+        let (chunk_size, thread_pool) = (self.embedding_chunk_size, Arc::clone(&self.thread_pool));
+        let (num_texts, embeddings) = py
+            .allow_threads(|| {
+                let texts = texts.as_strs()?;
+                let embeddings =
+                    embed_in_parallel(embedder.as_ref(), &texts, chunk_size, &thread_pool)?;
+                Ok::<_, anyhow::Error>((texts.len(), embeddings))
+            })
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Error embedding batch: {}",
+                    e
+                ))
+            })?;
+
+        if output == "arrow" {
+            let vectors = vectors_to_fixed_size_list(embeddings, self.vector_dim, None)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+            let array = pyo3_arrow::PyArray::from_array_ref(Arc::new(vectors));
+            return Ok(array.into_pyobject(py)?.into_any().unbind());
+        }
+        let embeddings = Array2::from_shape_vec((num_texts, self.vector_dim), embeddings)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("{}", e)))?;
+        Ok(PyArray2::from_owned_array(py, embeddings)
+            .into_any()
+            .unbind())
     }
 
-    /// Embeds a single string using the static embedding model.
+    /// Embeds a single string using the configured embedding model.
     fn embed_string(&self, text: &str) -> PyResult<Vec<f32>> {
         let text_vec = vec![text];
         let result = self.embedder.embed_batch_vec(&text_vec).map_err(|e| {
@@ -170,6 +958,62 @@ impl DfEmbedderRust {
     }
 }
 
+impl DfEmbedderRust {
+    /// Store of a table of the database, reading through the dataset cache
+    fn table_store(&self, table_name: &str) -> LanceStore {
+        LanceStore::new_with_database(
+            &self.database_path.to_string_lossy(),
+            table_name,
+            self.vector_dim,
+        )
+        .with_dataset_cache(Arc::clone(&self.datasets))
+    }
+}
+
+fn indexing_result_to_dict(result: &IndexingResult) -> HashMap<String, usize> {
+    HashMap::from([
+        ("rows_indexed".to_string(), result.rows_indexed),
+        ("zero_vectors".to_string(), result.zero_vectors),
+        ("non_finite_vectors".to_string(), result.non_finite_vectors),
+        ("skipped_rows".to_string(), result.skipped_rows),
+        ("null_vectors".to_string(), result.null_vectors),
+    ])
+}
+
+/// Whether a search returns a table of rows ("records" or "arrow") rather than texts ("text")
+fn parse_rows_output(output: &str) -> PyResult<bool> {
+    match output {
+        "text" => Ok(false),
+        "records" | "arrow" => Ok(true),
+        other => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+            "Unknown output '{}': expected 'text', 'records' or 'arrow'",
+            other
+        ))),
+    }
+}
+
+/// Returns the rows found by a search as an Arrow table, or only their texts
+fn rows_to_py(py: Python<'_>, batch: RecordBatch, rows_output: bool) -> PyResult<PyObject> {
+    if !rows_output {
+        let texts: Vec<String> = batch
+            .column_by_name(TEXT_COLUMN)
+            .and_then(|column| column.as_string_opt::<i32>())
+            .map(|texts| texts.iter().flatten().map(str::to_string).collect())
+            .unwrap_or_default();
+        return Ok(texts.into_pyobject(py)?.into_any().unbind());
+    }
+    let schema = batch.schema();
+    let table = pyo3_arrow::PyTable::try_new(vec![batch], schema)?;
+    Ok(table.into_pyobject(py)?.into_any().unbind())
+}
+
+fn parse_metric(metric: Option<String>) -> PyResult<Option<DistanceMetric>> {
+    metric
+        .map(|metric| metric.parse::<DistanceMetric>())
+        .transpose()
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{}", e)))
+}
+
 /// Define the Python module.
 #[pymodule]
 fn dfembed(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
@@ -0,0 +1,252 @@
+use crate::embedding::projection::Projection;
+use crate::embedding::token_weights::TokenWeights;
+use crate::storage::vector_index::VectorIndexInfo;
+use anyhow::Result;
+use lance::dataset::Dataset;
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// When a cached table handle looks for versions written since it was opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Consistency {
+    /// Before every read, by listing the versions of the table
+    #[default]
+    Latest,
+    /// At most once per interval, so that reads in between may miss recent writes
+    Interval(Duration),
+    /// Never: reads stay on the version first opened until the handle is refreshed
+    Pinned,
+}
+
+impl Consistency {
+    /// Parses "latest", "interval" with its refresh interval, or "pinned"
+    pub fn new(mode: &str, interval: Option<Duration>) -> Result<Self> {
+        match (mode, interval) {
+            ("latest", None) => Ok(Consistency::Latest),
+            ("interval", Some(interval)) => Ok(Consistency::Interval(interval)),
+            ("pinned", None) => Ok(Consistency::Pinned),
+            ("interval", None) => Err(anyhow::anyhow!(
+                "The 'interval' consistency needs a refresh interval"
+            )),
+            ("latest" | "pinned", Some(_)) => Err(anyhow::anyhow!(
+                "A refresh interval only applies to the 'interval' consistency"
+            )),
+            (other, _) => Err(anyhow::anyhow!(
+                "Unknown consistency '{}': expected 'latest', 'interval' or 'pinned'",
+                other
+            )),
+        }
+    }
+}
+
+/// The files dfembed keeps next to a table and the metadata of its vector index, each loaded
+/// on first use for one version of it
+#[derive(Default)]
+pub struct TableArtifacts {
+    pub(crate) token_weights: OnceCell<Option<Arc<TokenWeights>>>,
+    pub(crate) projection: OnceCell<Option<Arc<Projection>>>,
+    pub(crate) vector_index: OnceCell<Option<VectorIndexInfo>>,
+}
+
+struct CachedDataset {
+    dataset: Dataset,
+    // when the handle was opened or last compared with the latest version
+    checked: Instant,
+    pinned: bool,
+    artifacts: Arc<TableArtifacts>,
+}
+
+/// Opened table handles shared between searches, so that repeated queries do not re-read the
+/// manifest and index metadata of the table, nor the artifacts stored next to it.
+/// Safe to share between threads.
+pub struct DatasetCache {
+    consistency: Consistency,
+    datasets: Mutex<HashMap<String, CachedDataset>>,
+}
+
+impl DatasetCache {
+    pub fn new(consistency: Consistency) -> Self {
+        Self {
+            consistency,
+            datasets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the handle of the table at `path`, opened on first use and moved to the latest
+    /// version of the table when the consistency asks for it
+    pub async fn get(&self, path: &str) -> Result<Dataset> {
+        let cached = self
+            .lock_datasets()
+            .get(path)
+            .map(|cached| (cached.dataset.clone(), cached.checked, cached.pinned));
+        let Some((mut dataset, checked, pinned)) = cached else {
+            let dataset = Dataset::open(path).await?;
+            let pinned = self.consistency == Consistency::Pinned;
+            self.insert(path, dataset.clone(), pinned);
+            return Ok(dataset);
+        };
+        let check = match self.consistency {
+            _ if pinned => false,
+            Consistency::Latest => true,
+            Consistency::Interval(interval) => checked.elapsed() >= interval,
+            Consistency::Pinned => false,
+        };
+        if !check {
+            return Ok(dataset);
+        }
+        // listing the versions is much cheaper than reopening the table
+        if dataset.latest_version_id().await? != dataset.version().version {
+            dataset.checkout_latest().await?;
+            self.insert(path, dataset.clone(), false);
+        } else if let Some(cached) = self.lock_datasets().get_mut(path) {
+            cached.checked = Instant::now();
+        }
+        Ok(dataset)
+    }
+
+    /// Returns the artifacts of `version` of the table at `path`. They are shared while the
+    /// cached handle stays on that version; other versions get artifacts of their own that
+    /// are not kept.
+    pub fn artifacts(&self, path: &str, version: u64) -> Arc<TableArtifacts> {
+        match self.lock_datasets().get(path) {
+            Some(cached) if cached.dataset.version().version == version => {
+                Arc::clone(&cached.artifacts)
+            }
+            _ => Arc::new(TableArtifacts::default()),
+        }
+    }
+
+    /// Pins the table at `path` to a version, by default its latest one, whatever the
+    /// consistency. Returns the pinned version.
+    pub async fn pin(&self, path: &str, version: Option<u64>) -> Result<u64> {
+        let dataset = Dataset::open(path).await?;
+        let dataset = match version {
+            Some(version) => dataset.checkout_version(version).await?,
+            None => dataset,
+        };
+        let version = dataset.version().version;
+        self.insert(path, dataset, true);
+        Ok(version)
+    }
+
+    /// Drops the handle of the table at `path`, pinned or not, so that the next read opens
+    /// its latest version
+    pub fn refresh(&self, path: &str) {
+        self.lock_datasets().remove(path);
+    }
+
+    /// Drops the handle of a table written by this process unless it is pinned, so that the
+    /// next read sees the write
+    pub fn written(&self, path: &str) {
+        let mut datasets = self.lock_datasets();
+        if datasets.get(path).is_some_and(|cached| !cached.pinned) {
+            datasets.remove(path);
+        }
+    }
+
+    fn insert(&self, path: &str, dataset: Dataset, pinned: bool) {
+        let cached = CachedDataset {
+            dataset,
+            checked: Instant::now(),
+            pinned,
+            artifacts: Arc::new(TableArtifacts::default()),
+        };
+        self.lock_datasets().insert(path.to_string(), cached);
+    }
+
+    // handles are inserted whole, so the map is consistent even if a holder panicked
+    fn lock_datasets(&self) -> MutexGuard<'_, HashMap<String, CachedDataset>> {
+        self.datasets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::lance::LanceStore;
+
+    #[test]
+    fn test_consistency_new() {
+        assert_eq!(
+            Consistency::new("latest", None).unwrap(),
+            Consistency::Latest
+        );
+        assert_eq!(
+            Consistency::new("interval", Some(Duration::from_secs(5))).unwrap(),
+            Consistency::Interval(Duration::from_secs(5))
+        );
+        assert!(Consistency::new("interval", None).is_err());
+        assert!(Consistency::new("pinned", Some(Duration::from_secs(5))).is_err());
+        assert!(Consistency::new("eventual", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dataset_cache() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "cached", 2);
+        store
+            .add_vectors(&["a"], &["a"], vec![Some(vec![1.0, 0.0])])
+            .await?;
+        let path = store.file_path();
+
+        let latest = DatasetCache::new(Consistency::Latest);
+        let pinned = DatasetCache::new(Consistency::Pinned);
+        let interval = DatasetCache::new(Consistency::Interval(Duration::from_secs(3600)));
+        for cache in [&latest, &pinned, &interval] {
+            assert_eq!(cache.get(path).await?.count_rows(None).await?, 1);
+        }
+        store
+            .add_vectors(&["b"], &["b"], vec![Some(vec![0.0, 1.0])])
+            .await?;
+        assert_eq!(latest.get(path).await?.count_rows(None).await?, 2);
+        assert_eq!(pinned.get(path).await?.count_rows(None).await?, 1);
+        assert_eq!(interval.get(path).await?.count_rows(None).await?, 1);
+        interval.written(path);
+        assert_eq!(interval.get(path).await?.count_rows(None).await?, 2);
+
+        // a pinned handle survives writes until it is refreshed
+        let version = latest.pin(path, Some(1)).await?;
+        assert_eq!(version, 1);
+        latest.written(path);
+        assert_eq!(latest.get(path).await?.count_rows(None).await?, 1);
+        latest.refresh(path);
+        assert_eq!(latest.get(path).await?.count_rows(None).await?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_table_artifacts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "artifacts", 2);
+        store
+            .add_vectors(&["a"], &["a"], vec![Some(vec![1.0, 0.0])])
+            .await?;
+        let path = store.file_path();
+        let cache = DatasetCache::new(Consistency::Latest);
+        let version = cache.get(path).await?.version().version;
+
+        // the artifacts of the cached version are shared between reads
+        let artifacts = cache.artifacts(path, version);
+        artifacts.token_weights.set(None).ok();
+        assert_eq!(cache.get(path).await?.version().version, version);
+        assert!(cache.artifacts(path, version).token_weights.get().is_some());
+        assert!(
+            cache
+                .artifacts(path, version + 1)
+                .token_weights
+                .get()
+                .is_none()
+        );
+
+        // a new version of the table starts without loaded artifacts
+        store
+            .add_vectors(&["b"], &["b"], vec![Some(vec![0.0, 1.0])])
+            .await?;
+        let latest = cache.get(path).await?.version().version;
+        assert_ne!(latest, version);
+        assert!(cache.artifacts(path, latest).token_weights.get().is_none());
+        Ok(())
+    }
+}
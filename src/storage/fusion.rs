@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::storage::quantization::SearchHit;
+
+/// Rank offset of reciprocal rank fusion, dampening the weight of the top ranks
+pub const RRF_K: f32 = 60.0;
+
+/// Number of rows fetched from each retriever per requested result
+pub const HYBRID_CANDIDATE_FACTOR: usize = 4;
+
+/// How the vector and full-text results of a hybrid search are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FusionMethod {
+    /// Reciprocal rank fusion, `sum(1 / (RRF_K + rank))` over the retrievers
+    Rrf,
+    /// Blend of the min-max normalized scores, `vector_weight * vector + (1 - vector_weight) * text`
+    Weighted { vector_weight: f32 },
+}
+
+impl FusionMethod {
+    /// The fusion of a search mode: `None` for a plain "vector" search, the parsed method
+    /// for a "hybrid" one
+    pub fn for_mode(mode: &str, name: &str, vector_weight: f32) -> anyhow::Result<Option<Self>> {
+        match mode {
+            "vector" => Ok(None),
+            "hybrid" => Ok(Some(Self::parse(name, vector_weight)?)),
+            other => Err(anyhow::anyhow!(
+                "Unknown search mode '{}': expected 'vector' or 'hybrid'",
+                other
+            )),
+        }
+    }
+
+    /// Parse a method name; `vector_weight` is only used by the weighted blend
+    pub fn parse(name: &str, vector_weight: f32) -> anyhow::Result<Self> {
+        match name.parse::<FusionKind>()? {
+            FusionKind::Rrf => Ok(FusionMethod::Rrf),
+            FusionKind::Weighted if (0.0..=1.0).contains(&vector_weight) => {
+                Ok(FusionMethod::Weighted { vector_weight })
+            }
+            FusionKind::Weighted => Err(anyhow::anyhow!(
+                "vector_weight must be between 0 and 1, got {}",
+                vector_weight
+            )),
+        }
+    }
+}
+
+enum FusionKind {
+    Rrf,
+    Weighted,
+}
+
+impl FromStr for FusionKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "rrf" => Ok(FusionKind::Rrf),
+            "weighted" => Ok(FusionKind::Weighted),
+            other => Err(anyhow::anyhow!(
+                "Unknown fusion '{}': expected 'rrf' or 'weighted'",
+                other
+            )),
+        }
+    }
+}
+
+/// A row found by a hybrid search, with its fused score and the scores of each retriever
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridHit {
+    pub row_id: u64,
+    pub text: String,
+    /// Fused score, higher is better
+    pub score: f32,
+    /// Distance to the query vector, when the vector search found the row
+    pub vector_distance: Option<f32>,
+    /// BM25 score, when the full-text search found the row
+    pub text_score: Option<f32>,
+}
+
+/// Fuse the vector hits (closest first) and the full-text hits (best BM25 score first, the
+/// score being held in `distance`), returning the k best rows by fused score
+pub fn fuse(
+    vector_hits: &[SearchHit],
+    text_hits: &[SearchHit],
+    method: FusionMethod,
+    k: usize,
+) -> Vec<HybridHit> {
+    // vector similarities and text scores, both higher is better
+    let vector_scores = match method {
+        FusionMethod::Rrf => rrf_scores(vector_hits.len()),
+        FusionMethod::Weighted { vector_weight } => {
+            let similarities: Vec<f32> = vector_hits.iter().map(|hit| -hit.distance).collect();
+            weighted(&min_max_normalize(&similarities), vector_weight)
+        }
+    };
+    let text_scores = match method {
+        FusionMethod::Rrf => rrf_scores(text_hits.len()),
+        FusionMethod::Weighted { vector_weight } => {
+            let scores: Vec<f32> = text_hits.iter().map(|hit| hit.distance).collect();
+            weighted(&min_max_normalize(&scores), 1.0 - vector_weight)
+        }
+    };
+
+    let mut fused: Vec<HybridHit> = Vec::new();
+    let mut positions: HashMap<u64, usize> = HashMap::new();
+    for (hit, score) in vector_hits.iter().zip(vector_scores) {
+        positions.insert(hit.row_id, fused.len());
+        fused.push(HybridHit {
+            row_id: hit.row_id,
+            text: hit.text.clone(),
+            score,
+            vector_distance: Some(hit.distance),
+            text_score: None,
+        });
+    }
+    for (hit, score) in text_hits.iter().zip(text_scores) {
+        match positions.get(&hit.row_id) {
+            Some(&position) => {
+                fused[position].score += score;
+                fused[position].text_score = Some(hit.distance);
+            }
+            None => {
+                positions.insert(hit.row_id, fused.len());
+                fused.push(HybridHit {
+                    row_id: hit.row_id,
+                    text: hit.text.clone(),
+                    score,
+                    vector_distance: None,
+                    text_score: Some(hit.distance),
+                });
+            }
+        }
+    }
+    // ties keep the vector order first, then the text order
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused.truncate(k);
+    fused
+}
+
+fn rrf_scores(len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|rank| 1.0 / (RRF_K + rank as f32 + 1.0))
+        .collect()
+}
+
+fn weighted(scores: &[f32], weight: f32) -> Vec<f32> {
+    scores.iter().map(|score| score * weight).collect()
+}
+
+// scale to [0, 1]; a single score, or equal scores, all map to 1
+fn min_max_normalize(scores: &[f32]) -> Vec<f32> {
+    let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    scores
+        .iter()
+        .map(|score| {
+            if max > min {
+                (score - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(rows: &[(u64, f32)]) -> Vec<SearchHit> {
+        rows.iter()
+            .map(|&(row_id, distance)| SearchHit {
+                distance,
+                row_id,
+                text: format!("row {}", row_id),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rrf() {
+        let vector_hits = hits(&[(1, 0.1), (2, 0.2), (3, 0.3)]);
+        let text_hits = hits(&[(3, 9.0), (4, 5.0)]);
+        let fused = fuse(&vector_hits, &text_hits, FusionMethod::Rrf, 4);
+        let rows: Vec<u64> = fused.iter().map(|hit| hit.row_id).collect();
+        // row 3 is found by both retrievers, and the tie of rows 2 and 4 keeps the vector order
+        assert_eq!(rows, vec![3, 1, 2, 4]);
+        assert_eq!(fused[0].vector_distance, Some(0.3));
+        assert_eq!(fused[0].text_score, Some(9.0));
+        assert!((fused[0].score - (1.0 / 63.0 + 1.0 / 61.0)).abs() < 1e-6);
+        assert_eq!(
+            (fused[1].text_score, fused[3].vector_distance),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_weighted() -> anyhow::Result<()> {
+        let vector_hits = hits(&[(1, 0.1), (2, 0.5)]);
+        let text_hits = hits(&[(2, 8.0), (3, 2.0)]);
+        let only_text = FusionMethod::parse("weighted", 0.0)?;
+        let rows: Vec<u64> = fuse(&vector_hits, &text_hits, only_text, 1)
+            .iter()
+            .map(|hit| hit.row_id)
+            .collect();
+        assert_eq!(rows, vec![2]);
+
+        let blend = FusionMethod::parse("weighted", 0.5)?;
+        let fused = fuse(&vector_hits, &text_hits, blend, 3);
+        assert_eq!(fused[0].score, 0.5);
+        assert!(FusionMethod::parse("weighted", 1.5).is_err());
+        assert!(FusionMethod::parse("max", 0.5).is_err());
+        assert_eq!(FusionMethod::for_mode("vector", "max", 2.0)?, None);
+        assert!(FusionMethod::for_mode("keyword", "rrf", 0.5).is_err());
+        Ok(())
+    }
+}
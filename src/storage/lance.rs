@@ -1,26 +1,188 @@
-use arrow::array::{FixedSizeListArray, Float32Array, StringArray};
-use arrow::datatypes::{DataType, Field, Float32Type, Schema};
+use arrow::array::{
+    Array, ArrayRef, AsArray, FixedSizeListArray, Float32Array, StringArray, UInt32Array,
+    UInt64Array,
+};
+use arrow::compute::take_record_batch;
+use arrow::datatypes::{DataType, Field, Float32Type, Int8Type, Schema, UInt8Type};
 use arrow::error::ArrowError;
 use arrow::record_batch::{RecordBatch, RecordBatchIterator};
-use futures::TryStreamExt;
+use futures::{StreamExt, TryStreamExt, stream};
 use lance::dataset::Dataset;
+use lance::dataset::scanner::Scanner;
 use lance::dataset::{WriteMode, WriteParams};
-use std::path::PathBuf;
+use lance_index::optimize::OptimizeOptions;
+use lance_index::scalar::{FullTextSearchQuery, InvertedIndexParams};
+use lance_index::{DatasetIndexExt, IndexType};
+use rayon::ThreadPool;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::debug;
 
-use crate::embedding::static_embeder::Embedder;
-
-
+use crate::embedding::coordinator::embed_in_parallel_weighted;
+use crate::embedding::embedder::Embedder;
+use crate::embedding::projection::{Projection, ProjectionKind};
+use crate::embedding::static_embeder::truncate_embedding;
+use crate::embedding::token_weights::{TokenWeighting, TokenWeights};
+use crate::storage::dataset_cache::{DatasetCache, TableArtifacts};
+use crate::storage::fusion::{FusionMethod, HYBRID_CANDIDATE_FACTOR, HybridHit, fuse};
+use crate::storage::quantization::{
+    BINARY_COLUMN, INT8_COLUMN, QuantizationOptions, RESCORE_FACTOR, SearchHit, TopK,
+    VectorQuantization, hamming_distance, int8_cosine_distance, quantize_binary, quantize_int8,
+};
+use crate::storage::scalar_index::ScalarIndexKind;
+use crate::storage::vector_index::{
+    DistanceMetric, VECTOR_INDEX_NAME, VectorIndexInfo, VectorIndexOptions, VectorIndexType,
+};
 
+#[derive(Clone)]
 pub struct LanceStore {
     schema: Arc<Schema>,
     file_path: String,
     vec_dim: usize,
+    quantization: QuantizationOptions,
+    payload: Vec<Field>,
+    datasets: Option<Arc<DatasetCache>>,
+}
+
+/// How `find_most_similar` searches the table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Rescore the candidates of a quantized search with the f32 vectors, when the table has them
+    pub rescore: bool,
+    /// SQL predicate on the stored columns, e.g. "year > 2010 AND genre = 'Comedy'"
+    pub filter: Option<String>,
+    /// Apply the filter before the vector search, so that k matching rows are returned.
+    /// Otherwise the k nearest rows are filtered afterwards and fewer of them may remain.
+    pub prefilter: bool,
+    /// Metric of this search, instead of the one of the vector index or of the table
+    pub metric: Option<DistanceMetric>,
+    /// IVF partitions searched by the vector index, more of them raising recall and latency
+    pub nprobes: Option<usize>,
+    /// Reranks `refine_factor * k` index candidates with the f32 vectors
+    pub refine_factor: Option<u32>,
+    /// HNSW candidate list size of an IVF_HNSW_SQ index search
+    pub ef: Option<usize>,
+    /// Search through the vector index when the table has one, otherwise scan all the vectors
+    pub use_index: bool,
 }
 
-const VECTOR_COLUMN: &str = "vector";
-const TEXT_COLUMN: &str = "text";
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            rescore: true,
+            filter: None,
+            prefilter: true,
+            metric: None,
+            nprobes: None,
+            refine_factor: None,
+            ef: None,
+            use_index: true,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Whether any parameter of the vector index search is set
+    fn tunes_index(&self) -> bool {
+        self.nprobes.is_some() || self.refine_factor.is_some() || self.ef.is_some()
+    }
+
+    /// Checks the vector index search parameters against the index of the table and the `k`
+    /// rows the search looks for
+    fn check_index_tuning(&self, index: Option<&VectorIndexInfo>, k: usize) -> anyhow::Result<()> {
+        if !self.tunes_index() {
+            return Ok(());
+        }
+        if !self.use_index {
+            return Err(anyhow::anyhow!(
+                "nprobes, refine_factor and ef tune the vector index search and cannot be used \
+                 without the index"
+            ));
+        }
+        let index = index.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Table has no vector index, nprobes, refine_factor and ef need one \
+                 (see create_vector_index)"
+            )
+        })?;
+        if self.nprobes == Some(0) || self.refine_factor == Some(0) || self.ef == Some(0) {
+            return Err(anyhow::anyhow!(
+                "nprobes, refine_factor and ef must be at least 1"
+            ));
+        }
+        if self.ef.is_some() && index.index_type != VectorIndexType::IvfHnswSq {
+            return Err(anyhow::anyhow!(
+                "ef tunes IVF_HNSW_SQ indexes, the table has an {} index",
+                index.index_type.as_str()
+            ));
+        }
+        if let Some(ef) = self.ef.filter(|&ef| ef < k) {
+            return Err(anyhow::anyhow!(
+                "ef ({}) must be at least the number of rows the vector search looks for ({})",
+                ef,
+                k
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Size of the vector data of a table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageStats {
+    pub rows: usize,
+    pub vector_dim: usize,
+    /// Bytes of f32 vector data per row, 0 when the table only stores quantized vectors
+    pub full_precision_bytes_per_row: usize,
+    /// Bytes of quantized vector data per row
+    pub quantized_bytes_per_row: usize,
+    /// Size of all the table files on disk, including older versions
+    pub disk_bytes: u64,
+}
+
+/// Recall@k of the quantized search against the exact f32 search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecallReport {
+    pub queries: usize,
+    pub k: usize,
+    pub recall: f64,
+    pub rescored_recall: f64,
+}
+
+pub const VECTOR_COLUMN: &str = "vector";
+pub const TEXT_COLUMN: &str = "text";
+const FILENAME_COLUMN: &str = "filename";
+/// Columns written by dfembed itself, which payload columns cannot replace
+const RESERVED_COLUMNS: [&str; 5] = [
+    FILENAME_COLUMN,
+    TEXT_COLUMN,
+    VECTOR_COLUMN,
+    INT8_COLUMN,
+    BINARY_COLUMN,
+];
+const ROW_ID_COLUMN: &str = "_rowid";
+const QUERY_INDEX_COLUMN: &str = "query_idx";
+const DISTANCE_COLUMN: &str = "_distance";
+const SCORE_COLUMN: &str = "_score";
+// Columns of hybrid search results
+const FUSED_SCORE_COLUMN: &str = "score";
+const VECTOR_DISTANCE_COLUMN: &str = "vector_distance";
+const TEXT_SCORE_COLUMN: &str = "text_score";
+
+// Table metadata keys describing how the stored vectors were produced
+pub const METADATA_VECTOR_DIM: &str = "dfembed.vector_dim";
+pub const METADATA_MODEL: &str = "dfembed.model";
+pub const METADATA_TOKEN_WEIGHTING: &str = "dfembed.token_weighting";
+pub const METADATA_PROJECTION: &str = "dfembed.projection";
+pub const METADATA_QUANTIZATION: &str = "dfembed.quantization";
+pub const METADATA_FULL_PRECISION: &str = "dfembed.full_precision";
+pub const METADATA_METRIC: &str = "dfembed.metric";
+
+// Directory inside the table holding the artifacts fitted on it
+const ARTIFACTS_DIR: &str = "_dfembed";
+const TOKEN_WEIGHTS_FILE: &str = "token_weights.safetensors";
+const PROJECTION_FILE: &str = "projection.safetensors";
 
 impl LanceStore {
     /// Creates a new LanceStore instance within a specified database directory.
@@ -53,82 +215,1165 @@ impl LanceStore {
             schema: Self::get_default_schema(vector_dim),
             file_path: file_path,
             vec_dim: vector_dim,
+            quantization: QuantizationOptions::default(),
+            payload: Vec::new(),
+            datasets: None,
+        }
+    }
+
+    /// Records the embedding model in the table metadata when the table is created.
+    pub fn with_model(self, model_id: &str) -> Self {
+        self.with_metadata(METADATA_MODEL, model_id)
+    }
+
+    /// Records the token weighting in the table metadata when the table is created.
+    pub fn with_token_weighting(self, weighting: TokenWeighting) -> Self {
+        self.with_metadata(METADATA_TOKEN_WEIGHTING, weighting.as_str())
+    }
+
+    /// Stores vectors of the projection output dimension, recording the projection kind
+    /// in the table metadata when the table is created.
+    pub fn with_projection(mut self, projection: &Projection) -> Self {
+        self.vec_dim = projection.output_dim();
+        self.with_vector_columns()
+            .with_metadata(METADATA_PROJECTION, projection.kind.as_str())
+    }
+
+    /// Stores quantized vectors next to, or instead of, the f32 vectors, recording the
+    /// quantization in the table metadata when the table is created.
+    pub fn with_quantization(mut self, quantization: QuantizationOptions) -> Self {
+        self.quantization = quantization;
+        let full_precision = quantization.keep_full_precision.to_string();
+        self.with_vector_columns()
+            .with_metadata(METADATA_QUANTIZATION, quantization.quantization.as_str())
+            .with_metadata(METADATA_FULL_PRECISION, &full_precision)
+    }
+
+    /// Records the distance metric of the table in its metadata when the table is created.
+    pub fn with_metric(self, metric: DistanceMetric) -> Self {
+        self.with_metadata(METADATA_METRIC, metric.as_str())
+    }
+
+    /// Stores these columns of the source table next to the vectors, for filtering and
+    /// returning them with the search results
+    pub fn with_payload(mut self, fields: Vec<Field>) -> Self {
+        self.payload = fields;
+        self.with_vector_columns()
+    }
+
+    /// Whether a column name is taken by the columns dfembed writes
+    pub fn is_reserved_column(name: &str) -> bool {
+        RESERVED_COLUMNS.contains(&name)
+    }
+
+    // rebuild the vector and payload columns for the current dimension and quantization,
+    // keeping the metadata
+    fn with_vector_columns(mut self) -> Self {
+        let mut metadata = self.schema.metadata().clone();
+        metadata.insert(METADATA_VECTOR_DIM.to_string(), self.vec_dim.to_string());
+        let schema = Self::get_schema(self.vec_dim, &self.quantization);
+        let fields: Vec<Field> = schema
+            .fields()
+            .iter()
+            .map(|field| field.as_ref().clone())
+            .chain(self.payload.iter().cloned())
+            .collect();
+        self.schema = Arc::new(Schema::new_with_metadata(fields, metadata));
+        self
+    }
+
+    fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        let mut metadata = self.schema.metadata().clone();
+        metadata.insert(key.to_string(), value.to_string());
+        self.schema = Arc::new(self.schema.as_ref().clone().with_metadata(metadata));
+        self
+    }
+
+    /// Reads the table through a cache of opened handles instead of opening it on every search.
+    pub fn with_dataset_cache(mut self, datasets: Arc<DatasetCache>) -> Self {
+        self.datasets = Some(datasets);
+        self
+    }
+
+    /// Path of the table directory
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Opens the table for a search, from the dataset cache when the store has one
+    async fn read_dataset(&self) -> anyhow::Result<Dataset> {
+        match &self.datasets {
+            Some(datasets) => datasets.get(&self.file_path).await,
+            None => Ok(Dataset::open(&self.file_path).await?),
+        }
+    }
+
+    /// Opens the table, returning `None` when it does not exist yet
+    pub async fn open_dataset(&self) -> anyhow::Result<Option<Dataset>> {
+        match Dataset::open(&self.file_path).await {
+            Ok(db) => Ok(Some(db)),
+            Err(lance::Error::DatasetNotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // the artifacts loaded for this version of the table, kept by the dataset cache
+    fn table_artifacts(&self, db: &Dataset) -> Arc<TableArtifacts> {
+        match &self.datasets {
+            Some(datasets) => datasets.artifacts(&self.file_path, db.version().version),
+            None => Arc::new(TableArtifacts::default()),
         }
     }
 
+    fn artifact_path(&self, file_name: &str) -> PathBuf {
+        Path::new(&self.file_path)
+            .join(ARTIFACTS_DIR)
+            .join(file_name)
+    }
+
+    /// Saves the token weights fitted on this table next to its data
+    pub fn save_token_weights(&self, weights: &TokenWeights) -> anyhow::Result<()> {
+        weights.save(&self.artifact_path(TOKEN_WEIGHTS_FILE))
+    }
+
+    /// Loads the token weights the table vectors were pooled with, if any, once per version
+    /// of the table when the store has a dataset cache
+    pub fn stored_token_weights(&self, db: &Dataset) -> anyhow::Result<Option<Arc<TokenWeights>>> {
+        let artifacts = self.table_artifacts(db);
+        let weights = artifacts.token_weights.get_or_try_init(|| {
+            let weighting = match db.schema().metadata.get(METADATA_TOKEN_WEIGHTING) {
+                Some(weighting) => weighting.parse::<TokenWeighting>()?,
+                None => return Ok::<_, anyhow::Error>(None),
+            };
+            if weighting == TokenWeighting::Mean {
+                return Ok(None);
+            }
+            let weights = TokenWeights::load(&self.artifact_path(TOKEN_WEIGHTS_FILE), weighting)?;
+            Ok(Some(Arc::new(weights)))
+        })?;
+        Ok(weights.clone())
+    }
+
+    /// Saves the projection fitted on this table next to its data
+    pub fn save_projection(&self, projection: &Projection) -> anyhow::Result<()> {
+        projection.save(&self.artifact_path(PROJECTION_FILE))
+    }
+
+    /// Loads the projection applied to the table vectors, if any, once per version of the
+    /// table when the store has a dataset cache
+    pub fn stored_projection(&self, db: &Dataset) -> anyhow::Result<Option<Arc<Projection>>> {
+        let artifacts = self.table_artifacts(db);
+        let projection = artifacts.projection.get_or_try_init(|| {
+            match db.schema().metadata.get(METADATA_PROJECTION) {
+                Some(kind) => Ok::<_, anyhow::Error>(Some(Arc::new(Projection::load(
+                    &self.artifact_path(PROJECTION_FILE),
+                    kind.parse::<ProjectionKind>()?,
+                )?))),
+                None => Ok(None),
+            }
+        })?;
+        Ok(projection.clone())
+    }
+
+    /// Reads which vector columns the table stores
+    pub fn stored_quantization(&self, db: &Dataset) -> anyhow::Result<QuantizationOptions> {
+        let metadata = &db.schema().metadata;
+        let quantization = match metadata.get(METADATA_QUANTIZATION) {
+            Some(quantization) => quantization.parse::<VectorQuantization>()?,
+            None => VectorQuantization::None,
+        };
+        let keep_full_precision = match metadata.get(METADATA_FULL_PRECISION) {
+            Some(full_precision) => full_precision.parse::<bool>()?,
+            None => true,
+        };
+        Ok(QuantizationOptions {
+            quantization,
+            keep_full_precision,
+        })
+    }
+
+    /// Reads the distance metric of the table; tables created without one use L2
+    pub fn stored_metric(&self, db: &Dataset) -> anyhow::Result<DistanceMetric> {
+        match db.schema().metadata.get(METADATA_METRIC) {
+            Some(metric) => metric.parse::<DistanceMetric>(),
+            None => Ok(DistanceMetric::default()),
+        }
+    }
+
+    /// Metric of a search: the requested one, else the one the vector index was built with,
+    /// else the one of the table
+    fn search_metric(
+        &self,
+        db: &Dataset,
+        index: Option<&VectorIndexInfo>,
+        options: &SearchOptions,
+    ) -> anyhow::Result<DistanceMetric> {
+        if let Some(metric) = options.metric {
+            return Ok(metric);
+        }
+        match index {
+            Some(index) => Ok(index.metric),
+            None => self.stored_metric(db),
+        }
+    }
+
+    /// Names of the payload columns stored in the table
+    pub fn stored_payload_columns(&self, db: &Dataset) -> Vec<String> {
+        db.schema()
+            .fields
+            .iter()
+            .map(|field| field.name.clone())
+            .filter(|name| !Self::is_reserved_column(name))
+            .collect()
+    }
+
+    /// Reads the dimension of the stored vectors
+    fn stored_vector_dim(&self, db: &Dataset) -> anyhow::Result<usize> {
+        match db.schema().metadata.get(METADATA_VECTOR_DIM) {
+            Some(dim) => Ok(dim.parse::<usize>()?),
+            None => Ok(self.vec_dim),
+        }
+    }
+
+    /// Reads how the vector index of this version of the table was built, if it has one,
+    /// from the index metadata of the table
+    pub async fn stored_vector_index(
+        &self,
+        db: &Dataset,
+    ) -> anyhow::Result<Option<VectorIndexInfo>> {
+        let artifacts = self.table_artifacts(db);
+        if let Some(index) = artifacts.vector_index.get() {
+            return Ok(*index);
+        }
+        let indices = db.load_indices().await?;
+        let index = if indices.iter().any(|index| index.name == VECTOR_INDEX_NAME) {
+            let statistics = db.index_statistics(VECTOR_INDEX_NAME).await?;
+            Some(VectorIndexInfo::from_statistics(&statistics)?)
+        } else {
+            None
+        };
+        // a concurrent search may have loaded the same metadata first
+        artifacts.vector_index.set(index).ok();
+        Ok(index)
+    }
+
     pub async fn find_most_similar(
         &self,
         query: &str,
         k: usize,
-        embedder: &Embedder,
+        embedder: &dyn Embedder,
+        options: &SearchOptions,
     ) -> anyhow::Result<Vec<String>> {
-        let query_embedding = embedder.embed_batch_vec(&[query])?;
-        let query_embedding = match query_embedding.first() {
-            Some(embedding) => embedding,
-            None => return Err(anyhow::anyhow!("Embedder returned no vector for the query")),
+        let db = self.read_dataset().await?;
+        let query_embedding = self.query_vector(&db, query, embedder)?;
+        let rows = self
+            .nearest_rows(&db, &query_embedding, k, false, options)
+            .await?;
+        debug!("Found {} similar results.", rows.len());
+        Ok(rows.into_iter().map(|hit| hit.text).collect())
+    }
+
+    /// Vector search returning the k nearest rows, closest first, as a batch with their row id
+    /// (`_rowid`), text, distance to the query (`_distance`) and the requested columns, by
+    /// default all the payload columns
+    pub async fn search_rows(
+        &self,
+        query: &str,
+        k: usize,
+        embedder: &dyn Embedder,
+        options: &SearchOptions,
+        columns: Option<&[String]>,
+    ) -> anyhow::Result<RecordBatch> {
+        let db = self.read_dataset().await?;
+        let query_embedding = self.query_vector(&db, query, embedder)?;
+        let hits = self
+            .nearest_rows(&db, &query_embedding, k, false, options)
+            .await?;
+        self.rows_batch(&db, &hits, columns).await
+    }
+
+    /// Like `search_rows`, for a query vector already in the space of the stored vectors, e.g.
+    /// produced by another model of the same dimension
+    pub async fn search_rows_by_vector(
+        &self,
+        vector: &[f32],
+        k: usize,
+        options: &SearchOptions,
+        columns: Option<&[String]>,
+    ) -> anyhow::Result<RecordBatch> {
+        let db = self.read_dataset().await?;
+        let table_dim = self.stored_vector_dim(&db)?;
+        if vector.len() != table_dim {
+            return Err(anyhow::anyhow!(
+                "Table vectors have {} dimensions but the query vector has {}",
+                table_dim,
+                vector.len()
+            ));
+        }
+        if vector.iter().all(|x| *x == 0.0) || vector.iter().any(|x| !x.is_finite()) {
+            return Err(anyhow::anyhow!(
+                "Query vector is zero or has non-finite values"
+            ));
+        }
+        let hits = self.nearest_rows(&db, vector, k, false, options).await?;
+        self.rows_batch(&db, &hits, columns).await
+    }
+
+    /// Like `search_rows`, for the rows nearest to the stored vector of a row ("more like
+    /// this"), leaving the row itself out of the results
+    pub async fn search_rows_like(
+        &self,
+        row_id: u64,
+        k: usize,
+        options: &SearchOptions,
+        columns: Option<&[String]>,
+    ) -> anyhow::Result<RecordBatch> {
+        let db = self.read_dataset().await?;
+        if !self.stored_quantization(&db)?.keep_full_precision {
+            return Err(anyhow::anyhow!(
+                "Table only stores quantized vectors, rows cannot be used as queries"
+            ));
+        }
+        let batch = db
+            .take_rows(&[row_id], db.schema().project(&[VECTOR_COLUMN])?)
+            .await?;
+        let vectors = list_column(&batch, VECTOR_COLUMN)?;
+        if vectors.is_empty() {
+            return Err(anyhow::anyhow!("Row {} not found", row_id));
+        }
+        if vectors.is_null(0) {
+            return Err(anyhow::anyhow!("Row {} has no vector", row_id));
+        }
+        let vector = vectors.value(0);
+        let vector = vector.as_primitive::<Float32Type>().values();
+        // the row is usually its own nearest neighbour
+        let mut hits = self
+            .nearest_rows(&db, vector, k + 1, false, options)
+            .await?;
+        hits.retain(|hit| hit.row_id != row_id);
+        hits.truncate(k);
+        self.rows_batch(&db, &hits, columns).await
+    }
+
+    /// Joins the search hits with the requested columns, by default all the payload columns
+    async fn rows_batch(
+        &self,
+        db: &Dataset,
+        hits: &[SearchHit],
+        columns: Option<&[String]>,
+    ) -> anyhow::Result<RecordBatch> {
+        let columns = match columns {
+            Some(columns) => columns.to_vec(),
+            None => self.stored_payload_columns(db),
         };
-        let db = Dataset::open(&self.file_path).await?;
+        self.hits_to_batch(db, hits, &columns).await
+    }
+
+    /// Vector searches of several queries, embedded together on the threads of `thread_pool`
+    /// and run as many at a time. Returns the rows found for every query, in query order, as one batch with
+    /// the index of their query (`query_idx`) followed by the columns of `search_rows`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn search_rows_batch(
+        &self,
+        queries: &[&str],
+        k: usize,
+        embedder: &dyn Embedder,
+        options: &SearchOptions,
+        columns: Option<&[String]>,
+        thread_pool: &ThreadPool,
+    ) -> anyhow::Result<RecordBatch> {
+        let db = self.read_dataset().await?;
+        let query_vectors = self.query_vectors(&db, queries, embedder, thread_pool)?;
+        let quantization = self.stored_quantization(&db)?;
+        let index = self.stored_vector_index(&db).await?;
+        let searches = query_vectors.into_iter().map(|query| {
+            let (store, db, options) = (self.clone(), db.clone(), options.clone());
+            tokio::spawn(async move {
+                store
+                    .search_nearest_rows(&db, &query, k, false, &options, quantization, index)
+                    .await
+            })
+        });
+        let results: Vec<Vec<SearchHit>> = stream::iter(searches)
+            .buffered(thread_pool.current_num_threads())
+            .map(|search| search?)
+            .try_collect()
+            .await?;
+
+        let mut query_indices = Vec::new();
+        let mut hits = Vec::new();
+        for (query_idx, query_hits) in results.into_iter().enumerate() {
+            query_indices.extend(std::iter::repeat_n(query_idx as u32, query_hits.len()));
+            hits.extend(query_hits);
+        }
+        let rows = self.rows_batch(&db, &hits, columns).await?;
+
+        let mut fields = vec![Field::new(QUERY_INDEX_COLUMN, DataType::UInt32, false)];
+        fields.extend(
+            rows.schema()
+                .fields()
+                .iter()
+                .map(|field| field.as_ref().clone()),
+        );
+        let mut arrays: Vec<ArrayRef> = vec![Arc::new(UInt32Array::from(query_indices))];
+        arrays.extend(rows.columns().iter().cloned());
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+    }
+
+    /// Joins the search hits with the requested columns of their rows
+    async fn hits_to_batch(
+        &self,
+        db: &Dataset,
+        hits: &[SearchHit],
+        columns: &[String],
+    ) -> anyhow::Result<RecordBatch> {
+        let row_ids: Vec<u64> = hits.iter().map(|hit| hit.row_id).collect();
+        let payload = self.payload_rows(db, &row_ids, columns).await?;
+
+        let mut fields = vec![
+            Field::new(ROW_ID_COLUMN, DataType::UInt64, false),
+            Field::new(TEXT_COLUMN, DataType::Utf8, false),
+            Field::new(DISTANCE_COLUMN, DataType::Float32, false),
+        ];
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(row_ids)),
+            Arc::new(StringArray::from_iter_values(
+                hits.iter().map(|hit| hit.text.as_str()),
+            )),
+            Arc::new(Float32Array::from_iter_values(
+                hits.iter().map(|hit| hit.distance),
+            )),
+        ];
+        for (field, column) in payload.schema().fields().iter().zip(payload.columns()) {
+            fields.push(field.as_ref().clone());
+            arrays.push(Arc::clone(column));
+        }
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+    }
+
+    /// Reads the requested columns of rows, in the order of `row_ids`, the text column aside
+    async fn payload_rows(
+        &self,
+        db: &Dataset,
+        row_ids: &[u64],
+        columns: &[String],
+    ) -> anyhow::Result<RecordBatch> {
+        let columns: Vec<&str> = columns
+            .iter()
+            .map(|column| column.as_str())
+            .filter(|column| *column != TEXT_COLUMN)
+            .collect();
+        let projection = db.schema().project(&columns).map_err(|e| {
+            anyhow::anyhow!(
+                "Cannot return the columns {:?}: {}, stored payload columns are {:?}",
+                columns,
+                e,
+                self.stored_payload_columns(db)
+            )
+        })?;
+        if row_ids.is_empty() || columns.is_empty() {
+            return Ok(RecordBatch::new_empty(Arc::new(Schema::from(&projection))));
+        }
+        // rows found by several queries of a batch are read once
+        let mut unique_row_ids = Vec::new();
+        let mut positions = HashMap::new();
+        let indices: Vec<u32> = row_ids
+            .iter()
+            .map(|row_id| {
+                *positions.entry(*row_id).or_insert_with(|| {
+                    unique_row_ids.push(*row_id);
+                    unique_row_ids.len() as u32 - 1
+                })
+            })
+            .collect();
+        let rows = db.take_rows(&unique_row_ids, projection).await?;
+        Ok(take_record_batch(&rows, &UInt32Array::from(indices))?)
+    }
+
+    /// Embeds a query the way the table vectors were produced
+    fn query_vector(
+        &self,
+        db: &Dataset,
+        query: &str,
+        embedder: &dyn Embedder,
+    ) -> anyhow::Result<Vec<f32>> {
+        self.check_query_model(db, embedder)?;
+        // the query is pooled with the token weights fitted on the table
+        let embeddings = match self.stored_token_weights(db)? {
+            Some(weights) => embedder.embed_batch_vec_weighted(&[query], &weights)?,
+            None => embedder.embed_batch_vec(&[query])?,
+        };
+        match self.fit_query_vectors(db, &[query], embeddings)?.pop() {
+            Some(embedding) => Ok(embedding),
+            None => Err(anyhow::anyhow!("Embedder returned no vector for the query")),
+        }
+    }
+
+    /// Embeds queries the way the table vectors were produced, in parallel on the threads of
+    /// `thread_pool`
+    fn query_vectors(
+        &self,
+        db: &Dataset,
+        queries: &[&str],
+        embedder: &dyn Embedder,
+        thread_pool: &ThreadPool,
+    ) -> anyhow::Result<Vec<Vec<f32>>> {
+        self.check_query_model(db, embedder)?;
+        let weights = self.stored_token_weights(db)?;
+        let embeddings = embed_in_parallel_weighted(
+            embedder,
+            queries,
+            weights.as_deref(),
+            queries.len().div_ceil(thread_pool.current_num_threads()),
+            thread_pool,
+        )?;
+        let embeddings = embeddings
+            .chunks_exact(embedder.embedding_dim())
+            .map(|embedding| embedding.to_vec())
+            .collect();
+        self.fit_query_vectors(db, queries, embeddings)
+    }
+
+    fn check_query_model(&self, db: &Dataset, embedder: &dyn Embedder) -> anyhow::Result<()> {
+        if let Some(model) = db.schema().metadata.get(METADATA_MODEL) {
+            if model != embedder.model_id() {
+                return Err(anyhow::anyhow!(
+                    "Table was indexed with model {} but the embedder uses {}",
+                    model,
+                    embedder.model_id()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Truncates and projects query embeddings the way the table vectors were, rejecting the
+    /// ones without a meaningful distance
+    fn fit_query_vectors(
+        &self,
+        db: &Dataset,
+        queries: &[&str],
+        embeddings: Vec<Vec<f32>>,
+    ) -> anyhow::Result<Vec<Vec<f32>>> {
+        if embeddings.len() != queries.len() {
+            return Err(anyhow::anyhow!(
+                "Embedder returned {} vectors for {} queries",
+                embeddings.len(),
+                queries.len()
+            ));
+        }
+        // the queries must be truncated the same way the table vectors were
+        let table_dim = self.stored_vector_dim(db)?;
+        // a projected table was fitted on vectors of the projection input dimension
+        let projection = self.stored_projection(db)?;
+        let input_dim = match &projection {
+            Some(projection) => projection.input_dim(),
+            None => table_dim,
+        };
+
+        let mut vectors = Vec::with_capacity(embeddings.len());
+        for (query, embedding) in queries.iter().zip(embeddings) {
+            let embedding = match input_dim {
+                dim if dim == embedding.len() => embedding,
+                dim if dim < embedding.len() => truncate_embedding(&embedding, dim),
+                dim => {
+                    return Err(anyhow::anyhow!(
+                        "Table vectors have {} dimensions but the embedder produces {}",
+                        dim,
+                        embedding.len()
+                    ));
+                }
+            };
+            let embedding = match &projection {
+                Some(projection) => projection.apply(&embedding),
+                None => embedding,
+            };
+
+            // an empty query pools no tokens, and its zero vector has no meaningful distance
+            if embedding.iter().all(|x| *x == 0.0) || embedding.iter().any(|x| !x.is_finite()) {
+                return Err(anyhow::anyhow!(
+                    "Query '{}' produced a zero or non-finite vector",
+                    query
+                ));
+            }
+            vectors.push(embedding);
+        }
+        Ok(vectors)
+    }
 
-        let query_embedding_arrow = Float32Array::from(query_embedding.clone());
+    /// Vector search returning the row ids and texts of the k nearest rows, closest first.
+    /// Quantized tables are searched on the quantized column unless `exact` is set, and the
+    /// candidates are rescored with the f32 vectors when `rescore` is set and they are stored.
+    /// An exact search, or one with `use_index` unset, scans the f32 vectors without the
+    /// vector index.
+    async fn nearest_rows(
+        &self,
+        db: &Dataset,
+        query: &[f32],
+        k: usize,
+        exact: bool,
+        options: &SearchOptions,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let quantization = self.stored_quantization(db)?;
+        let index = self.stored_vector_index(db).await?;
+        self.search_nearest_rows(db, query, k, exact, options, quantization, index)
+            .await
+    }
+
+    /// `nearest_rows` with the quantization and vector index of the table already resolved,
+    /// for searches that run many queries against the same dataset version
+    #[allow(clippy::too_many_arguments)]
+    async fn search_nearest_rows(
+        &self,
+        db: &Dataset,
+        query: &[f32],
+        k: usize,
+        exact: bool,
+        options: &SearchOptions,
+        quantization: QuantizationOptions,
+        index: Option<VectorIndexInfo>,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let metric = self.search_metric(db, index.as_ref(), options)?;
+        options.check_index_tuning(index.as_ref(), k)?;
+        // without the index the search scans the f32 vectors
+        let exact = exact || !options.use_index;
+        match quantization.quantization.column() {
+            Some(_) if !exact && options.tunes_index() => {
+                return Err(anyhow::anyhow!(
+                    "Searches of {} quantized tables scan the quantized vectors without the \
+                     vector index, nprobes, refine_factor and ef do not apply",
+                    quantization.quantization.as_str()
+                ));
+            }
+            Some(column) if !exact => {
+                let rescore = options.rescore && quantization.keep_full_precision;
+                return self
+                    .quantized_nearest_rows(
+                        db,
+                        query,
+                        k,
+                        column,
+                        quantization,
+                        rescore.then_some(metric),
+                        options.filter.as_deref(),
+                    )
+                    .await;
+            }
+            _ if !quantization.keep_full_precision => {
+                return Err(anyhow::anyhow!(
+                    "Table only stores {} quantized vectors, exact search is not available",
+                    quantization.quantization.as_str()
+                ));
+            }
+            _ => {}
+        }
 
-        // Configure the scanner first
+        let query_embedding_arrow = Float32Array::from(query.to_vec());
         let mut scanner = db.scan();
         scanner.project(&[TEXT_COLUMN])?;
         scanner.nearest(VECTOR_COLUMN, &query_embedding_arrow, k)?;
+        // a metric other than the one of the vector index searches without the index
+        scanner.distance_metric(metric.distance_type());
+        if exact {
+            scanner.use_index(false);
+        }
+        if let Some(nprobes) = options.nprobes {
+            scanner.nprobs(nprobes);
+        }
+        if let Some(refine_factor) = options.refine_factor {
+            scanner.refine(refine_factor);
+        }
+        if let Some(ef) = options.ef {
+            scanner.ef(ef);
+        }
+        if let Some(filter) = &options.filter {
+            apply_filter(&mut scanner, filter)?;
+            scanner.prefilter(options.prefilter);
+        }
+        scanner.with_row_id();
 
-        // Convert scanner to stream and collect
         let results_batches = scanner
             .try_into_stream()
-            .await? // Get the stream
-            .try_collect::<Vec<_>>() // Collect RecordBatches from the stream
+            .await?
+            .try_collect::<Vec<_>>()
             .await?;
+        let mut rows = Vec::new();
+        for batch in results_batches {
+            let texts = string_column(&batch, TEXT_COLUMN)?;
+            let row_ids = row_id_column(&batch)?;
+            let distances = float_column(&batch, DISTANCE_COLUMN)?;
+            for row in 0..batch.num_rows() {
+                if texts.is_valid(row) {
+                    debug!("  Found similar text: {}", texts.value(row));
+                    rows.push(SearchHit {
+                        distance: distances.value(row),
+                        row_id: row_ids.value(row),
+                        text: texts.value(row).to_string(),
+                    });
+                }
+            }
+        }
+        Ok(rows)
+    }
 
-        debug!("Found {} similar results.", results_batches.len());
-        let mut found_texts: Vec<String> = Vec::new();
+    /// Scans the quantized column for the nearest candidates, then, given the metric to rescore
+    /// with, reorders them by their exact distance. The filter is applied to the scanned rows,
+    /// as a prefilter.
+    #[allow(clippy::too_many_arguments)]
+    async fn quantized_nearest_rows(
+        &self,
+        db: &Dataset,
+        query: &[f32],
+        k: usize,
+        column: &str,
+        quantization: QuantizationOptions,
+        rescore: Option<DistanceMetric>,
+        filter: Option<&str>,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let query_int8 = quantize_int8(query);
+        let query_bits = quantize_binary(query);
+        let num_candidates = if rescore.is_some() {
+            k * RESCORE_FACTOR
+        } else {
+            k
+        };
+        let mut top = TopK::new(num_candidates);
 
-        for batch in results_batches {
-            // Iterate directly over collected Vec<RecordBatch>
-            // info!("Batch: {:?}", batch);
-            // Example: Access the 'text' column (assuming it's the first projected column)
-            if let Some(text_col) = batch.column(0).as_any().downcast_ref::<StringArray>() {
-                for text_val_opt in text_col.iter() {
-                    if let Some(text_val) = text_val_opt {
-                        debug!("  Found similar text: {}", text_val);
-                        found_texts.push(text_val.to_string());
+        let mut scanner = db.scan();
+        scanner.project(&[TEXT_COLUMN, column])?;
+        if let Some(filter) = filter {
+            apply_filter(&mut scanner, filter)?;
+        }
+        scanner.with_row_id();
+        let mut stream = scanner.try_into_stream().await?;
+        while let Some(batch) = stream.try_next().await? {
+            let vectors = list_column(&batch, column)?;
+            let texts = string_column(&batch, TEXT_COLUMN)?;
+            let row_ids = row_id_column(&batch)?;
+            for row in 0..batch.num_rows() {
+                if vectors.is_null(row) || texts.is_null(row) {
+                    continue;
+                }
+                let vector = vectors.value(row);
+                let distance = match quantization.quantization {
+                    VectorQuantization::Int8 => int8_cosine_distance(
+                        &query_int8,
+                        vector.as_primitive::<Int8Type>().values(),
+                    ),
+                    VectorQuantization::Binary => {
+                        hamming_distance(&query_bits, vector.as_primitive::<UInt8Type>().values())
+                            as f32
                     }
+                    VectorQuantization::None => unreachable!("table has a quantized column"),
+                };
+                if top.accepts(distance) {
+                    top.push(SearchHit {
+                        distance,
+                        row_id: row_ids.value(row),
+                        text: texts.value(row).to_string(),
+                    });
                 }
             }
         }
 
-        Ok(found_texts)
+        let mut candidates = top.into_sorted_vec();
+        if let (Some(metric), false) = (rescore, candidates.is_empty()) {
+            let row_ids: Vec<u64> = candidates.iter().map(|c| c.row_id).collect();
+            let batch = db
+                .take_rows(&row_ids, db.schema().project(&[VECTOR_COLUMN])?)
+                .await?;
+            let vectors = list_column(&batch, VECTOR_COLUMN)?;
+            for (row, candidate) in candidates.iter_mut().enumerate() {
+                candidate.distance = if vectors.is_null(row) {
+                    f32::INFINITY
+                } else {
+                    metric.distance(
+                        query,
+                        vectors.value(row).as_primitive::<Float32Type>().values(),
+                    )
+                };
+            }
+            candidates.sort();
+        }
+        candidates.truncate(k);
+        Ok(candidates)
+    }
+
+    /// Builds a full-text (inverted) index on each of the columns, replacing existing ones,
+    /// so that hybrid searches can run BM25 queries on them
+    pub async fn create_full_text_index(&self, columns: &[String]) -> anyhow::Result<()> {
+        let mut db = Dataset::open(&self.file_path).await?;
+        for column in columns {
+            db.create_index(
+                &[column.as_str()],
+                IndexType::Inverted,
+                Some(format!("{}_fts", column)),
+                &InvertedIndexParams::default(),
+                true,
+            )
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to build a full-text index on '{}': {}", column, e)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Builds the ANN index on the vector column, replacing an existing one, and returns its
+    /// type and metric
+    pub async fn create_vector_index(
+        &self,
+        options: &VectorIndexOptions,
+    ) -> anyhow::Result<VectorIndexInfo> {
+        let mut db = Dataset::open(&self.file_path).await?;
+        if !self.stored_quantization(&db)?.keep_full_precision {
+            return Err(anyhow::anyhow!(
+                "Table only stores quantized vectors, a vector index needs the '{}' column",
+                VECTOR_COLUMN
+            ));
+        }
+        let num_rows = db.count_rows(None).await?;
+        let metric = match options.metric {
+            Some(metric) => metric,
+            None => self.stored_metric(&db)?,
+        };
+        let params = options.to_params(num_rows, self.stored_vector_dim(&db)?, metric)?;
+        debug!(
+            "Building {} index on {} rows",
+            options.index_type.as_str(),
+            num_rows
+        );
+        db.create_index(
+            &[VECTOR_COLUMN],
+            IndexType::Vector,
+            Some(VECTOR_INDEX_NAME.to_string()),
+            &params,
+            true,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to build the vector index: {}", e))?;
+        Ok(VectorIndexInfo {
+            index_type: options.index_type,
+            metric,
+        })
+    }
+
+    /// Builds a scalar index on a column of the table, replacing an existing one, so that
+    /// filters on it read the index instead of scanning the column. The text and vector
+    /// columns are searched through their own indexes and cannot get one.
+    pub async fn create_scalar_index(
+        &self,
+        column: &str,
+        kind: ScalarIndexKind,
+    ) -> anyhow::Result<()> {
+        if [TEXT_COLUMN, VECTOR_COLUMN, INT8_COLUMN, BINARY_COLUMN].contains(&column) {
+            return Err(anyhow::anyhow!(
+                "Column '{}' is searched through the vector or full-text index and cannot get a \
+                 scalar index",
+                column
+            ));
+        }
+        let mut db = Dataset::open(&self.file_path).await?;
+        if db.schema().field(column).is_none() {
+            return Err(anyhow::anyhow!(
+                "Column '{}' not found in the table, stored payload columns are {:?}",
+                column,
+                self.stored_payload_columns(&db)
+            ));
+        }
+        db.create_index(
+            &[column],
+            kind.index_type(),
+            Some(ScalarIndexKind::index_name(column)),
+            &kind.params(),
+            true,
+        )
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to build a {} index on '{}': {}",
+                kind.as_str(),
+                column,
+                e
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Number of rows of the table
+    pub async fn count_rows(&self) -> anyhow::Result<usize> {
+        let db = self.read_dataset().await?;
+        Ok(db.count_rows(None).await?)
+    }
+
+    /// Adds the rows appended since the indices were built to all the indices of the table
+    pub async fn optimize_indices(&self) -> anyhow::Result<()> {
+        let mut db = Dataset::open(&self.file_path).await?;
+        if !db.load_indices().await?.is_empty() {
+            db.optimize_indices(&OptimizeOptions::default()).await?;
+        }
+        Ok(())
+    }
+
+    /// BM25 search on the full-text indexed columns, best score first.
+    /// The score is held in the `distance` of the hits.
+    async fn full_text_rows(
+        &self,
+        db: &Dataset,
+        query: &str,
+        limit: usize,
+        options: &SearchOptions,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let mut scanner = db.scan();
+        scanner.project(&[TEXT_COLUMN])?;
+        scanner.full_text_search(
+            FullTextSearchQuery::new(query.to_string()).limit(Some(limit as i64)),
+        )?;
+        if let Some(filter) = &options.filter {
+            apply_filter(&mut scanner, filter)?;
+            scanner.prefilter(options.prefilter);
+        }
+        scanner.with_row_id();
+        let batches = scanner
+            .try_into_stream()
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "Full-text search failed, was the table indexed with full-text columns? {}",
+                    e
+                )
+            })?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut rows = Vec::new();
+        for batch in batches {
+            let texts = string_column(&batch, TEXT_COLUMN)?;
+            let row_ids = row_id_column(&batch)?;
+            let scores = float_column(&batch, SCORE_COLUMN)?;
+            for row in 0..batch.num_rows() {
+                if texts.is_valid(row) {
+                    rows.push(SearchHit {
+                        distance: scores.value(row),
+                        row_id: row_ids.value(row),
+                        text: texts.value(row).to_string(),
+                    });
+                }
+            }
+        }
+        rows.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+        Ok(rows)
+    }
+
+    /// Runs the vector search and the full-text search and fuses their results
+    pub async fn hybrid_search(
+        &self,
+        query: &str,
+        k: usize,
+        embedder: &dyn Embedder,
+        fusion: FusionMethod,
+        options: &SearchOptions,
+    ) -> anyhow::Result<Vec<HybridHit>> {
+        let db = self.read_dataset().await?;
+        self.fused_rows(&db, query, k, embedder, fusion, options)
+            .await
+    }
+
+    /// Like `hybrid_search`, returning the fused rows as one batch with their `_rowid`, `text`,
+    /// fused `score`, the `vector_distance` and BM25 `text_score` of each retriever (null when
+    /// it did not find the row) and the requested columns, by default the payload columns
+    pub async fn hybrid_search_rows(
+        &self,
+        query: &str,
+        k: usize,
+        embedder: &dyn Embedder,
+        fusion: FusionMethod,
+        options: &SearchOptions,
+        columns: Option<&[String]>,
+    ) -> anyhow::Result<RecordBatch> {
+        let db = self.read_dataset().await?;
+        let hits = self
+            .fused_rows(&db, query, k, embedder, fusion, options)
+            .await?;
+        let columns = match columns {
+            Some(columns) => columns.to_vec(),
+            None => self.stored_payload_columns(&db),
+        };
+        let row_ids: Vec<u64> = hits.iter().map(|hit| hit.row_id).collect();
+        let payload = self.payload_rows(&db, &row_ids, &columns).await?;
+
+        let mut fields = vec![
+            Field::new(ROW_ID_COLUMN, DataType::UInt64, false),
+            Field::new(TEXT_COLUMN, DataType::Utf8, false),
+            Field::new(FUSED_SCORE_COLUMN, DataType::Float32, false),
+            Field::new(VECTOR_DISTANCE_COLUMN, DataType::Float32, true),
+            Field::new(TEXT_SCORE_COLUMN, DataType::Float32, true),
+        ];
+        let mut arrays: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(row_ids)),
+            Arc::new(StringArray::from_iter_values(
+                hits.iter().map(|hit| hit.text.as_str()),
+            )),
+            Arc::new(Float32Array::from_iter_values(
+                hits.iter().map(|hit| hit.score),
+            )),
+            Arc::new(Float32Array::from_iter(
+                hits.iter().map(|hit| hit.vector_distance),
+            )),
+            Arc::new(Float32Array::from_iter(
+                hits.iter().map(|hit| hit.text_score),
+            )),
+        ];
+        for (field, column) in payload.schema().fields().iter().zip(payload.columns()) {
+            fields.push(field.as_ref().clone());
+            arrays.push(Arc::clone(column));
+        }
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+    }
+
+    // the vector and full-text results of a query on one version of the table, fused
+    async fn fused_rows(
+        &self,
+        db: &Dataset,
+        query: &str,
+        k: usize,
+        embedder: &dyn Embedder,
+        fusion: FusionMethod,
+        options: &SearchOptions,
+    ) -> anyhow::Result<Vec<HybridHit>> {
+        let num_candidates = k * HYBRID_CANDIDATE_FACTOR;
+        let query_embedding = self.query_vector(db, query, embedder)?;
+        let vector_hits = self
+            .nearest_rows(db, &query_embedding, num_candidates, false, options)
+            .await?;
+        let text_hits = self
+            .full_text_rows(db, query, num_candidates, options)
+            .await?;
+        debug!(
+            "Fusing {} vector and {} full-text results",
+            vector_hits.len(),
+            text_hits.len()
+        );
+        Ok(fuse(&vector_hits, &text_hits, fusion, k))
+    }
+
+    /// Reports the number of rows and the size of their vector data
+    pub async fn storage_stats(&self) -> anyhow::Result<StorageStats> {
+        let db = Dataset::open(&self.file_path).await?;
+        let quantization = self.stored_quantization(&db)?;
+        let vector_dim = self.stored_vector_dim(&db)?;
+        let quantized_bytes_per_row = quantization.quantization.bytes_per_vector(vector_dim);
+        Ok(StorageStats {
+            rows: db.count_rows(None).await?,
+            vector_dim,
+            full_precision_bytes_per_row: quantization.bytes_per_row(vector_dim)
+                - quantized_bytes_per_row,
+            quantized_bytes_per_row,
+            disk_bytes: directory_size(Path::new(&self.file_path))?,
+        })
+    }
+
+    /// Measures how many of the exact k nearest rows the quantized search finds,
+    /// with and without rescoring. The table must store both vector columns.
+    pub async fn quantization_recall(
+        &self,
+        queries: &[&str],
+        k: usize,
+        embedder: &dyn Embedder,
+    ) -> anyhow::Result<RecallReport> {
+        let db = Dataset::open(&self.file_path).await?;
+        let quantization = self.stored_quantization(&db)?;
+        if quantization.quantization == VectorQuantization::None {
+            return Err(anyhow::anyhow!("Table does not store quantized vectors"));
+        }
+        if !quantization.keep_full_precision {
+            return Err(anyhow::anyhow!(
+                "Recall needs the f32 vectors, but the table only stores quantized vectors"
+            ));
+        }
+
+        let quantized_options = SearchOptions {
+            rescore: false,
+            ..Default::default()
+        };
+        let rescored_options = SearchOptions::default();
+        let (mut found, mut found_rescored, mut expected) = (0usize, 0usize, 0usize);
+        for query in queries {
+            let query_embedding = self.query_vector(&db, query, embedder)?;
+            let exact = self
+                .nearest_rows(&db, &query_embedding, k, true, &quantized_options)
+                .await?;
+            let quantized = self
+                .nearest_rows(&db, &query_embedding, k, false, &quantized_options)
+                .await?;
+            let rescored = self
+                .nearest_rows(&db, &query_embedding, k, false, &rescored_options)
+                .await?;
+            let exact: HashSet<u64> = exact.into_iter().map(|hit| hit.row_id).collect();
+            expected += exact.len();
+            found += quantized
+                .iter()
+                .filter(|hit| exact.contains(&hit.row_id))
+                .count();
+            found_rescored += rescored
+                .iter()
+                .filter(|hit| exact.contains(&hit.row_id))
+                .count();
+        }
+        let ratio = |found: usize| match expected {
+            0 => 0.0,
+            expected => found as f64 / expected as f64,
+        };
+        Ok(RecallReport {
+            queries: queries.len(),
+            k,
+            recall: ratio(found),
+            rescored_recall: ratio(found_rescored),
+        })
     }
 
     pub async fn add_vectors(
         &self,
         file_name: &[&str],
         text: &[&str],
-        vectors: Vec<Vec<f32>>,
+        vectors: Vec<Option<Vec<f32>>>,
+    ) -> anyhow::Result<()> {
+        self.add_rows(file_name, text, vectors, None).await
+    }
+
+    /// Appends rows with their vectors and, when the table stores payload columns, the
+    /// payload columns of the same rows
+    pub async fn add_rows(
+        &self,
+        file_name: &[&str],
+        text: &[&str],
+        vectors: Vec<Option<Vec<f32>>>,
+        payload: Option<&RecordBatch>,
     ) -> anyhow::Result<()> {
         let key_array = StringArray::from_iter_values(file_name);
         let text_array = StringArray::from_iter_values(text);
-        let vectors_array = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
-            vectors
-                .into_iter()
-                .map(|v| Some(v.into_iter().map(|i| Some(i)))),
-            self.vec_dim as i32,
-        );
+        let mut columns: Vec<ArrayRef> = vec![Arc::new(key_array), Arc::new(text_array)];
+        let quantized_array = self
+            .quantization
+            .quantization
+            .quantize_array(&vectors, self.vec_dim)?;
+        if self.quantization.keep_full_precision {
+            let vectors_array = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+                vectors
+                    .into_iter()
+                    .map(|v| v.map(|v| v.into_iter().map(|i| Some(i)))),
+                self.vec_dim as i32,
+            );
+            columns.push(Arc::new(vectors_array));
+        }
+        columns.extend(quantized_array);
+        if let Some(payload) = payload {
+            columns.extend(payload.columns().iter().cloned());
+        }
         let batches = vec![
-            Ok(RecordBatch::try_new(
-                self.schema.clone(),
-                vec![
-                    Arc::new(key_array),
-                    Arc::new(text_array),
-                    Arc::new(vectors_array),
-                ],
-            )?)
-            .map_err(|e: Box<dyn std::error::Error + Send + Sync>| {
-                ArrowError::from_external_error(e)
-            }),
+            Ok(RecordBatch::try_new(self.schema.clone(), columns)?).map_err(
+                |e: Box<dyn std::error::Error + Send + Sync>| ArrowError::from_external_error(e),
+            ),
         ];
         let batch_iterator = RecordBatchIterator::new(batches, self.schema.clone());
         // Define write parameters (e.g. overwrite dataset)
@@ -136,33 +1381,95 @@ impl LanceStore {
             mode: WriteMode::Append,
             ..Default::default()
         };
+        // e.g. payload columns other than those the table was created with
         Dataset::write(batch_iterator, &self.file_path, Some(write_params))
             .await
-            .unwrap();
+            .map_err(|e| anyhow::anyhow!("Failed to append rows to {}: {}", self.file_path, e))?;
         Ok(())
     }
 
     /// Get the default schema for the VecDB
     pub fn get_default_schema(vector_dim: usize) -> Arc<Schema> {
-        Arc::new(Schema::new(vec![
-            Field::new("filename", DataType::Utf8, false),
-            Field::new("text", DataType::Utf8, false),
-            Field::new(
-                "vector",
+        Self::get_schema(vector_dim, &QuantizationOptions::default())
+    }
+
+    /// Get the schema for the VecDB with the vector columns of the given quantization
+    pub fn get_schema(vector_dim: usize, quantization: &QuantizationOptions) -> Arc<Schema> {
+        let metadata = HashMap::from([(METADATA_VECTOR_DIM.to_string(), vector_dim.to_string())]);
+        let mut fields = vec![
+            Field::new(FILENAME_COLUMN, DataType::Utf8, false),
+            Field::new(TEXT_COLUMN, DataType::Utf8, false),
+        ];
+        if quantization.keep_full_precision {
+            fields.push(Field::new(
+                VECTOR_COLUMN,
                 DataType::FixedSizeList(
                     Arc::new(Field::new("item", DataType::Float32, true)),
                     vector_dim as i32,
                 ),
                 true,
-            ),
-        ]))
+            ));
+        }
+        fields.extend(quantization.quantization.field(vector_dim));
+        Arc::new(Schema::new_with_metadata(fields, metadata))
+    }
+}
+
+fn apply_filter(scanner: &mut Scanner, filter: &str) -> anyhow::Result<()> {
+    scanner
+        .filter(filter)
+        .map_err(|e| anyhow::anyhow!("Invalid filter '{}': {}", filter, e))?;
+    Ok(())
+}
+
+fn string_column<'a>(batch: &'a RecordBatch, name: &str) -> anyhow::Result<&'a StringArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_string_opt::<i32>())
+        .ok_or_else(|| anyhow::anyhow!("Missing string column '{}'", name))
+}
+
+fn list_column<'a>(batch: &'a RecordBatch, name: &str) -> anyhow::Result<&'a FixedSizeListArray> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_fixed_size_list_opt())
+        .ok_or_else(|| anyhow::anyhow!("Missing vector column '{}'", name))
+}
+
+fn float_column<'a>(batch: &'a RecordBatch, name: &str) -> anyhow::Result<&'a Float32Array> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_primitive_opt())
+        .ok_or_else(|| anyhow::anyhow!("Missing column '{}'", name))
+}
+
+fn row_id_column(batch: &RecordBatch) -> anyhow::Result<&UInt64Array> {
+    batch
+        .column_by_name(ROW_ID_COLUMN)
+        .and_then(|column| column.as_primitive_opt())
+        .ok_or_else(|| anyhow::anyhow!("Missing row id column"))
+}
+
+// total size of the files below a directory
+fn directory_size(path: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
     }
+    Ok(size)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::embedding::static_embeder::Embedder;
+    use crate::embedding::static_embeder::StaticEmbedder;
+    use crate::storage::dataset_cache::Consistency;
     use std::fs;
     use std::path::Path;
 
@@ -190,9 +1497,9 @@ mod tests {
         ];
         let vector_dim = 3;
         let vectors = vec![
-            vec![1.0, 2.0, 3.0],
-            vec![4.0, 5.0, 6.0],
-            vec![7.0, 8.0, 9.0],
+            Some(vec![1.0, 2.0, 3.0]),
+            Some(vec![4.0, 5.0, 6.0]),
+            Some(vec![7.0, 8.0, 9.0]),
         ];
 
         // Initialize LanceStore using the test db and table names
@@ -221,6 +1528,26 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_add_null_vectors() -> anyhow::Result<()> {
+        // rows whose embedding was rejected are stored with a null vector
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "nulls", 2);
+        store
+            .add_vectors(
+                &["a", "b", "c"],
+                &["a", "b", "c"],
+                vec![Some(vec![1.0, 0.0]), None, Some(vec![0.0, 1.0])],
+            )
+            .await?;
+
+        let db = Dataset::open(store.file_path()).await?;
+        assert_eq!(db.count_rows(None).await?, 3);
+        let null_filter = format!("{} IS NULL", VECTOR_COLUMN);
+        assert_eq!(db.count_rows(Some(null_filter)).await?, 1);
+        Ok(())
+    }
+
     #[test]
     fn test_new_with_database_paths() {
         let table_name = "test_table";
@@ -255,6 +1582,527 @@ mod tests {
         // Temp directory is automatically cleaned up when `temp_dir_os` goes out of scope
     }
 
+    #[tokio::test]
+    async fn test_quantized_search() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let quantization = QuantizationOptions {
+            quantization: VectorQuantization::Int8,
+            keep_full_precision: true,
+        };
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "quantized", 3)
+            .with_quantization(quantization);
+        let texts = ["x", "y", "near x", "null"];
+        let vectors = vec![
+            Some(vec![1.0, 0.0, 0.0]),
+            Some(vec![0.0, 1.0, 0.0]),
+            Some(vec![0.8, 0.6, 0.0]),
+            None,
+        ];
+        store.add_vectors(&texts, &texts, vectors).await?;
+
+        let db = Dataset::open(&store.file_path).await?;
+        assert_eq!(store.stored_quantization(&db)?, quantization);
+        for rescore in [false, true] {
+            let options = SearchOptions {
+                rescore,
+                ..Default::default()
+            };
+            let rows = store
+                .nearest_rows(&db, &[1.0, 0.0, 0.0], 2, false, &options)
+                .await?;
+            let texts: Vec<&str> = rows.iter().map(|hit| hit.text.as_str()).collect();
+            assert_eq!(texts, vec!["x", "near x"]);
+        }
+
+        let stats = store.storage_stats().await?;
+        assert_eq!((stats.rows, stats.vector_dim), (4, 3));
+        assert_eq!(
+            (
+                stats.full_precision_bytes_per_row,
+                stats.quantized_bytes_per_row
+            ),
+            (12, 3)
+        );
+        assert!(stats.disk_bytes > 0);
+        Ok(())
+    }
+
+    /// A table of two films with a `year` payload column, "up" (2009) on the first axis and
+    /// "inside out" (2015) on the second
+    async fn year_store(database: &Path, table_name: &str) -> anyhow::Result<LanceStore> {
+        let year = Field::new("year", DataType::Int32, true);
+        let store = LanceStore::new_with_database(&database.to_string_lossy(), table_name, 2)
+            .with_payload(vec![year.clone()]);
+        let payload = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![year])),
+            vec![Arc::new(arrow::array::Int32Array::from(vec![2009, 2015]))],
+        )?;
+        let texts = ["up", "inside out"];
+        let vectors = vec![Some(vec![1.0, 0.0]), Some(vec![0.0, 1.0])];
+        store
+            .add_rows(&texts, &texts, vectors, Some(&payload))
+            .await?;
+        Ok(store)
+    }
+
+    #[tokio::test]
+    async fn test_payload_scalar_index() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = year_store(dir.path(), "payload").await?;
+
+        let db = Dataset::open(&store.file_path).await?;
+        assert_eq!(store.stored_payload_columns(&db), vec!["year"]);
+        for prefilter in [true, false] {
+            let options = SearchOptions {
+                filter: Some("year > 2010".to_string()),
+                prefilter,
+                ..Default::default()
+            };
+            let rows = store
+                .nearest_rows(&db, &[1.0, 0.0], 1, false, &options)
+                .await?;
+            let texts: Vec<&str> = rows.iter().map(|hit| hit.text.as_str()).collect();
+            // the postfilter drops the nearest row and has nothing left to return
+            let expected = if prefilter {
+                vec!["inside out"]
+            } else {
+                vec![]
+            };
+            assert_eq!(texts, expected);
+        }
+        let hits = store
+            .nearest_rows(&db, &[1.0, 0.0], 2, false, &SearchOptions::default())
+            .await?;
+        let batch = store
+            .hits_to_batch(&db, &hits, &["year".to_string()])
+            .await?;
+        let names: Vec<&str> = batch
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![ROW_ID_COLUMN, TEXT_COLUMN, DISTANCE_COLUMN, "year"]
+        );
+        let years = batch
+            .column(3)
+            .as_primitive::<arrow::datatypes::Int32Type>();
+        assert_eq!(years.values().to_vec(), vec![2009, 2015]);
+        assert_eq!(float_column(&batch, DISTANCE_COLUMN)?.value(0), 0.0);
+        assert!(
+            store
+                .hits_to_batch(&db, &hits, &["genre".to_string()])
+                .await
+                .is_err()
+        );
+
+        let invalid = SearchOptions {
+            filter: Some("year >".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            store
+                .nearest_rows(&db, &[1.0, 0.0], 1, false, &invalid)
+                .await
+                .is_err()
+        );
+        store
+            .create_scalar_index("year", ScalarIndexKind::BTree)
+            .await?;
+        assert!(
+            store
+                .create_scalar_index("genre", ScalarIndexKind::Bitmap)
+                .await
+                .is_err()
+        );
+        // the vector and text columns keep their own indexes
+        for column in [VECTOR_COLUMN, INT8_COLUMN, BINARY_COLUMN, TEXT_COLUMN] {
+            assert!(
+                store
+                    .create_scalar_index(column, ScalarIndexKind::BTree)
+                    .await
+                    .is_err()
+            );
+        }
+        let indices = Dataset::open(&store.file_path)
+            .await?
+            .load_indices()
+            .await?;
+        let names: Vec<&str> = indices.iter().map(|index| index.name.as_str()).collect();
+        assert_eq!(names, vec!["scalar_year_idx"]);
+        assert!(LanceStore::is_reserved_column(VECTOR_COLUMN));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_rows_schema_mismatch() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        year_store(dir.path(), "mismatch").await?;
+        // the same table written again with another payload column
+        let genre = Field::new("genre", DataType::Utf8, true);
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "mismatch", 2)
+            .with_payload(vec![genre.clone()]);
+        let payload = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![genre])),
+            vec![Arc::new(StringArray::from(vec!["animation"]))],
+        )?;
+        let result = store
+            .add_rows(
+                &["coco"],
+                &["coco"],
+                vec![Some(vec![1.0, 1.0])],
+                Some(&payload),
+            )
+            .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_table_metric() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "metric", 2)
+            .with_metric(DistanceMetric::Cosine);
+        let texts = ["long", "short"];
+        let vectors = vec![Some(vec![10.0, 0.0]), Some(vec![0.9, 0.1])];
+        store.add_vectors(&texts, &texts, vectors).await?;
+
+        let db = Dataset::open(&store.file_path).await?;
+        assert_eq!(store.stored_metric(&db)?, DistanceMetric::Cosine);
+        // the cosine distance ignores the norm, the L2 distance does not
+        for (metric, nearest) in [(None, "long"), (Some(DistanceMetric::L2), "short")] {
+            let options = SearchOptions {
+                metric,
+                ..Default::default()
+            };
+            let rows = store
+                .nearest_rows(&db, &[1.0, 0.0], 1, false, &options)
+                .await?;
+            assert_eq!(rows[0].text, nearest);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hnsw_vector_index() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "hnsw", 4);
+        let vector = |i: usize| {
+            let (a, b) = (i as f32 * 0.5, i as f32 * 1.3);
+            vec![a.cos(), a.sin(), b.cos(), b.sin()]
+        };
+        let texts: Vec<String> = (0..100).map(|i| format!("row {}", i)).collect();
+        let texts: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+        let vectors = (0..100).map(|i| Some(vector(i))).collect();
+        store.add_vectors(&texts, &texts, vectors).await?;
+        assert_eq!(
+            store
+                .stored_vector_index(&Dataset::open(store.file_path()).await?)
+                .await?,
+            None
+        );
+
+        let options = VectorIndexOptions {
+            index_type: VectorIndexType::IvfHnswSq,
+            num_partitions: Some(2),
+            ..Default::default()
+        };
+        let info = store.create_vector_index(&options).await?;
+        let db = Dataset::open(store.file_path()).await?;
+        assert_eq!(store.stored_vector_index(&db).await?, Some(info));
+        assert_eq!(info.metric, DistanceMetric::L2);
+
+        // the graph search finds a stored vector itself
+        let search = SearchOptions {
+            ef: Some(64),
+            ..Default::default()
+        };
+        let rows = store
+            .nearest_rows(&db, &vector(42), 3, false, &search)
+            .await?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].text, "row 42");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pinned_vector_index() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let datasets = Arc::new(DatasetCache::new(Consistency::Pinned));
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "pinned", 4)
+            .with_dataset_cache(Arc::clone(&datasets));
+        let texts: Vec<String> = (0..100).map(|i| format!("row {}", i)).collect();
+        let texts: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+        let vectors = (0..100)
+            .map(|i| Some(vec![i as f32, 1.0, (i % 7) as f32, (i % 3) as f32]))
+            .collect();
+        store.add_vectors(&texts, &texts, vectors).await?;
+        let pinned = store.read_dataset().await?;
+        assert_eq!(store.stored_vector_index(&pinned).await?, None);
+
+        let options = VectorIndexOptions {
+            index_type: VectorIndexType::IvfHnswSq,
+            num_partitions: Some(2),
+            ..Default::default()
+        };
+        let info = store.create_vector_index(&options).await?;
+        // the pinned handle stays on a version without the index
+        let db = store.read_dataset().await?;
+        assert_eq!(db.version().version, pinned.version().version);
+        assert_eq!(store.stored_vector_index(&db).await?, None);
+        let latest = Dataset::open(store.file_path()).await?;
+        assert_eq!(store.stored_vector_index(&latest).await?, Some(info));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_index_tuning() {
+        let pq = VectorIndexInfo {
+            index_type: VectorIndexType::IvfPq,
+            metric: DistanceMetric::L2,
+        };
+        let hnsw = VectorIndexInfo {
+            index_type: VectorIndexType::IvfHnswSq,
+            ..pq
+        };
+        let tuned = |nprobes, refine_factor, ef| SearchOptions {
+            nprobes,
+            refine_factor,
+            ef,
+            ..Default::default()
+        };
+        assert!(
+            SearchOptions::default()
+                .check_index_tuning(None, 10)
+                .is_ok()
+        );
+        assert!(
+            tuned(Some(20), Some(5), None)
+                .check_index_tuning(Some(&pq), 10)
+                .is_ok()
+        );
+        assert!(
+            tuned(Some(20), None, Some(64))
+                .check_index_tuning(Some(&hnsw), 10)
+                .is_ok()
+        );
+        // ef only applies to HNSW graphs
+        assert!(
+            tuned(None, None, Some(64))
+                .check_index_tuning(Some(&pq), 10)
+                .is_err()
+        );
+        assert!(
+            tuned(Some(20), None, None)
+                .check_index_tuning(None, 10)
+                .is_err()
+        );
+        assert!(
+            tuned(Some(0), None, None)
+                .check_index_tuning(Some(&pq), 10)
+                .is_err()
+        );
+        let exact = SearchOptions {
+            use_index: false,
+            ..tuned(Some(20), None, None)
+        };
+        assert!(exact.check_index_tuning(Some(&pq), 10).is_err());
+        // the HNSW candidate list holds at least the rows searched for
+        assert!(
+            tuned(None, None, Some(10))
+                .check_index_tuning(Some(&hnsw), 10)
+                .is_ok()
+        );
+        assert!(
+            tuned(None, None, Some(9))
+                .check_index_tuning(Some(&hnsw), 10)
+                .is_err()
+        );
+    }
+
+    /// Embeds "x" on the first axis and any other text on the second one
+    struct AxisEmbedder;
+
+    impl Embedder for AxisEmbedder {
+        fn model_id(&self) -> &str {
+            "axis"
+        }
+
+        fn embedding_dim(&self) -> usize {
+            2
+        }
+
+        fn embed_batch_vec(&self, texts: &[&str]) -> anyhow::Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| match *text {
+                    "x" => vec![1.0, 0.0],
+                    _ => vec![0.0, 1.0],
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_rows_batch() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = year_store(dir.path(), "batch").await?;
+
+        let options = SearchOptions::default();
+        let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(2).build()?;
+        let batch = store
+            .search_rows_batch(
+                &["x", "y", "x"],
+                1,
+                &AxisEmbedder,
+                &options,
+                None,
+                &thread_pool,
+            )
+            .await?;
+        let query_indices = batch
+            .column(0)
+            .as_primitive::<arrow::datatypes::UInt32Type>();
+        assert_eq!(query_indices.values().to_vec(), vec![0, 1, 2]);
+        let texts: Vec<&str> = string_column(&batch, TEXT_COLUMN)?
+            .iter()
+            .flatten()
+            .collect();
+        assert_eq!(texts, vec!["up", "inside out", "up"]);
+        // the row found by two queries is returned with each of them
+        let years = batch
+            .column_by_name("year")
+            .unwrap()
+            .as_primitive::<arrow::datatypes::Int32Type>();
+        assert_eq!(years.values().to_vec(), vec![2009, 2015, 2009]);
+
+        let empty = store
+            .search_rows_batch(&[], 1, &AxisEmbedder, &options, None, &thread_pool)
+            .await?;
+        assert_eq!(empty.num_rows(), 0);
+        assert_eq!(empty.schema().field(0).name(), QUERY_INDEX_COLUMN);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "hybrid", 2);
+        // the query embeds on the second axis, next to every row but the zeppelin one
+        let mut texts: Vec<String> = (0..10).map(|i| format!("film number {}", i)).collect();
+        texts.push("zeppelin airship".to_string());
+        let texts: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+        let mut vectors: Vec<Option<Vec<f32>>> =
+            (0..10).map(|i| Some(vec![i as f32 * 0.01, 1.0])).collect();
+        vectors.push(Some(vec![1.0, 0.0]));
+        store.add_vectors(&texts, &texts, vectors).await?;
+        store
+            .create_full_text_index(&[TEXT_COLUMN.to_string()])
+            .await?;
+
+        let options = SearchOptions::default();
+        let vector_hits = store
+            .find_most_similar("zeppelin", texts.len(), &AxisEmbedder, &options)
+            .await?;
+        assert_eq!(vector_hits.last().unwrap(), "zeppelin airship");
+
+        let hits = store
+            .hybrid_search("zeppelin", 2, &AxisEmbedder, FusionMethod::Rrf, &options)
+            .await?;
+        assert_eq!(hits.len(), 2);
+        let zeppelin = hits
+            .iter()
+            .find(|hit| hit.text == "zeppelin airship")
+            .expect("the exact token match is fused into the results");
+        assert!(zeppelin.text_score.is_some_and(|score| score > 0.0));
+        // the vector search ranked it below its candidates
+        assert_eq!(zeppelin.vector_distance, None);
+
+        let batch = store
+            .hybrid_search_rows(
+                "zeppelin",
+                2,
+                &AxisEmbedder,
+                FusionMethod::Rrf,
+                &options,
+                Some(&["filename".to_string()]),
+            )
+            .await?;
+        let names: Vec<&str> = batch
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ROW_ID_COLUMN,
+                TEXT_COLUMN,
+                FUSED_SCORE_COLUMN,
+                VECTOR_DISTANCE_COLUMN,
+                TEXT_SCORE_COLUMN,
+                "filename"
+            ]
+        );
+        let row_ids: Vec<u64> = row_id_column(&batch)?.values().to_vec();
+        assert_eq!(
+            row_ids,
+            hits.iter().map(|hit| hit.row_id).collect::<Vec<_>>()
+        );
+        // the texts are stored as their own file names
+        let filenames: Vec<&str> = string_column(&batch, "filename")?
+            .iter()
+            .flatten()
+            .collect();
+        let texts: Vec<&str> = hits.iter().map(|hit| hit.text.as_str()).collect();
+        assert_eq!(filenames, texts);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_by_vector_and_row() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = LanceStore::new_with_database(&dir.path().to_string_lossy(), "like", 2);
+        let texts = ["east", "east north east", "north"];
+        let vectors = vec![
+            Some(vec![1.0, 0.0]),
+            Some(vec![0.9, 0.1]),
+            Some(vec![0.0, 1.0]),
+        ];
+        store.add_vectors(&texts, &texts, vectors).await?;
+
+        let options = SearchOptions::default();
+        let batch = store
+            .search_rows_by_vector(&[1.0, 0.0], 1, &options, None)
+            .await?;
+        assert_eq!(string_column(&batch, TEXT_COLUMN)?.value(0), "east");
+        let east = row_id_column(&batch)?.value(0);
+        assert!(
+            store
+                .search_rows_by_vector(&[1.0, 0.0, 0.0], 1, &options, None)
+                .await
+                .is_err()
+        );
+        assert!(
+            store
+                .search_rows_by_vector(&[0.0, 0.0], 1, &options, None)
+                .await
+                .is_err()
+        );
+
+        // the row itself is left out
+        let batch = store.search_rows_like(east, 2, &options, None).await?;
+        let texts: Vec<&str> = string_column(&batch, TEXT_COLUMN)?
+            .iter()
+            .flatten()
+            .collect();
+        assert_eq!(texts, vec!["east north east", "north"]);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_find_most_similar() {
         // Define test database and table names
@@ -271,7 +2119,7 @@ mod tests {
         fs::create_dir_all(test_db).expect("Failed to create test database directory");
 
         // Initialize the real Embedder
-        let embedder = Embedder::new().expect("Failed to create real Embedder for test");
+        let embedder = StaticEmbedder::new().expect("Failed to create real Embedder for test");
         let vector_dim = 1024;
 
         // Define test data
@@ -301,6 +2149,7 @@ mod tests {
         let store = LanceStore::new_with_database(test_db, test_table_name, vector_dim);
 
         // Add vectors generated by the real embedder
+        let vectors = vectors.into_iter().map(Some).collect();
         store
             .add_vectors(&filenames, &texts, vectors)
             .await
@@ -311,7 +2160,9 @@ mod tests {
         let k = 1;
 
         // Use the actual find_most_similar function
-        let found_texts_result = store.find_most_similar(query_text, k, &embedder).await;
+        let found_texts_result = store
+            .find_most_similar(query_text, k, &embedder, &SearchOptions::default())
+            .await;
 
         // Verify the results
         assert!(
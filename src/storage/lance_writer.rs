@@ -1,4 +1,6 @@
 use crate::storage::lance::LanceStore;
+use arrow::compute::concat_batches;
+use arrow::record_batch::RecordBatch;
 use crossbeam::channel::Receiver;
 use std::sync::Arc;
 use std::thread;
@@ -9,51 +11,75 @@ use tracing::info;
 #[derive(Debug)]
 pub struct EmbeddingBatch {
     pub texts: Vec<String>,
-    pub embeddings: Vec<Vec<f32>>,
+    /// `None` for rows stored with a null vector
+    pub embeddings: Vec<Option<Vec<f32>>>,
+    /// Payload columns of the rows, when the table stores any
+    pub payload: Option<RecordBatch>,
 }
 
+/// Writes the received embeddings to the store, returning the number of rows written
 pub fn start_writing_thread(
     store: &LanceStore,
     receive_from_writer: Receiver<EmbeddingBatch>,
     write_buffer_size: usize,
     rt: Arc<tokio::runtime::Runtime>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<usize> {
     info!("Starting writer thread id {:?}", thread::current().id());
-    let mut write_buffer = EmbeddingBatch {
-        texts: Vec::new(),
-        embeddings: Vec::new(),
-    };
+    let mut write_buffer = WriteBuffer::default();
+    let mut rows_written = 0;
 
     while let Ok(embedding_batch) = receive_from_writer.recv() {
         write_buffer.texts.extend(embedding_batch.texts);
         write_buffer.embeddings.extend(embedding_batch.embeddings);
+        write_buffer.payloads.extend(embedding_batch.payload);
 
         if write_buffer.texts.len() >= write_buffer_size {
-            if let Err(e) = write_embedding_buffer(store, &mut write_buffer, &rt) {
-                error!("Error writing embedding buffer: {}", e);
+            match write_embedding_buffer(store, &mut write_buffer, &rt) {
+                Ok(rows) => rows_written += rows,
+                Err(e) => error!("Error writing embedding buffer: {}", e),
             }
         }
     }
     if write_buffer.texts.len() > 0 {
-        if let Err(e) = write_embedding_buffer(store, &mut write_buffer, &rt) {
-            error!("Error writing remaining embedding buffer: {}", e);
+        match write_embedding_buffer(store, &mut write_buffer, &rt) {
+            Ok(rows) => rows_written += rows,
+            Err(e) => error!("Error writing remaining embedding buffer: {}", e),
         }
     }
     info!("Writer thread finished - closing channel");
     drop(receive_from_writer);
-    Ok(())
+    Ok(rows_written)
+}
+
+/// Rows received from the embedding workers and not written yet
+#[derive(Default)]
+struct WriteBuffer {
+    texts: Vec<String>,
+    embeddings: Vec<Option<Vec<f32>>>,
+    payloads: Vec<RecordBatch>,
 }
 
 fn write_embedding_buffer(
     store: &LanceStore,
-    embedding_buffer: &mut EmbeddingBatch,
+    embedding_buffer: &mut WriteBuffer,
     rt: &Arc<tokio::runtime::Runtime>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<usize> {
     let texts: Vec<&str> = embedding_buffer.texts.iter().map(|s| s.as_str()).collect();
-    rt.block_on(store.add_vectors(&texts, &texts, embedding_buffer.embeddings.clone()))?;
+    let payload = match embedding_buffer.payloads.first() {
+        Some(first) => Some(concat_batches(&first.schema(), &embedding_buffer.payloads)?),
+        None => None,
+    };
+    rt.block_on(store.add_rows(
+        &texts,
+        &texts,
+        embedding_buffer.embeddings.clone(),
+        payload.as_ref(),
+    ))?;
+    let rows = embedding_buffer.texts.len();
     embedding_buffer.texts.clear();
     embedding_buffer.embeddings.clear();
-    Ok(())
+    embedding_buffer.payloads.clear();
+    Ok(rows)
 }
 
 pub fn start_parallel_writers_shared(
@@ -62,7 +88,7 @@ pub fn start_parallel_writers_shared(
     write_buffer_size: usize,
     num_writers: usize,
     rt: Arc<tokio::runtime::Runtime>,
-) -> Vec<JoinHandle<anyhow::Result<()>>> {
+) -> Vec<JoinHandle<anyhow::Result<usize>>> {
     (0..num_writers)
         .map(|_| {
             let store = Arc::clone(&store);
@@ -1,2 +1,7 @@
+pub mod dataset_cache;
+pub mod fusion;
 pub mod lance;
 pub mod lance_writer;
+pub mod quantization;
+pub mod scalar_index;
+pub mod vector_index;
@@ -0,0 +1,341 @@
+use arrow::array::{ArrayRef, FixedSizeListArray, Int8Array, UInt8Array};
+use arrow::buffer::NullBuffer;
+use arrow::datatypes::{DataType, Field};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Column holding the int8 quantized vectors
+pub const INT8_COLUMN: &str = "vector_int8";
+/// Column holding the sign bits of the vectors, packed 8 per byte
+pub const BINARY_COLUMN: &str = "vector_binary";
+
+/// Number of quantized candidates gathered per requested result before rescoring
+pub const RESCORE_FACTOR: usize = 4;
+
+/// Compact encoding of the stored vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VectorQuantization {
+    /// Only the f32 vectors are stored
+    #[default]
+    None,
+    /// One byte per dimension, scaled by the largest absolute value of the vector
+    Int8,
+    /// One bit per dimension holding its sign, compared with the Hamming distance
+    Binary,
+}
+
+impl VectorQuantization {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VectorQuantization::None => "none",
+            VectorQuantization::Int8 => "int8",
+            VectorQuantization::Binary => "binary",
+        }
+    }
+
+    /// Name of the quantized vector column
+    pub fn column(&self) -> Option<&'static str> {
+        match self {
+            VectorQuantization::None => None,
+            VectorQuantization::Int8 => Some(INT8_COLUMN),
+            VectorQuantization::Binary => Some(BINARY_COLUMN),
+        }
+    }
+
+    /// Bytes taken by one quantized vector of `dim` dimensions
+    pub fn bytes_per_vector(&self, dim: usize) -> usize {
+        match self {
+            VectorQuantization::None => 0,
+            VectorQuantization::Int8 => dim,
+            VectorQuantization::Binary => dim.div_ceil(8),
+        }
+    }
+
+    /// Arrow field of the quantized vector column
+    pub fn field(&self, dim: usize) -> Option<Field> {
+        let (name, item_type) = match self {
+            VectorQuantization::None => return None,
+            VectorQuantization::Int8 => (INT8_COLUMN, DataType::Int8),
+            VectorQuantization::Binary => (BINARY_COLUMN, DataType::UInt8),
+        };
+        Some(Field::new(
+            name,
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", item_type, true)),
+                self.bytes_per_vector(dim) as i32,
+            ),
+            true,
+        ))
+    }
+
+    /// Quantize the vectors of a batch into the quantized vector column
+    pub fn quantize_array(
+        &self,
+        vectors: &[Option<Vec<f32>>],
+        dim: usize,
+    ) -> anyhow::Result<Option<ArrayRef>> {
+        let field = match self.field(dim) {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        let item_field = match field.data_type() {
+            DataType::FixedSizeList(item_field, _) => Arc::clone(item_field),
+            _ => unreachable!("quantized fields are fixed size lists"),
+        };
+        let width = self.bytes_per_vector(dim);
+        let nulls = NullBuffer::from(vectors.iter().map(|v| v.is_some()).collect::<Vec<_>>());
+        let array: ArrayRef = match self {
+            VectorQuantization::Int8 => {
+                let values: Vec<i8> = vectors
+                    .iter()
+                    .flat_map(|v| match v {
+                        Some(v) => quantize_int8(v),
+                        None => vec![0; width],
+                    })
+                    .collect();
+                Arc::new(FixedSizeListArray::try_new(
+                    item_field,
+                    width as i32,
+                    Arc::new(Int8Array::from(values)),
+                    Some(nulls),
+                )?)
+            }
+            VectorQuantization::Binary => {
+                let values: Vec<u8> = vectors
+                    .iter()
+                    .flat_map(|v| match v {
+                        Some(v) => quantize_binary(v),
+                        None => vec![0; width],
+                    })
+                    .collect();
+                Arc::new(FixedSizeListArray::try_new(
+                    item_field,
+                    width as i32,
+                    Arc::new(UInt8Array::from(values)),
+                    Some(nulls),
+                )?)
+            }
+            VectorQuantization::None => unreachable!(),
+        };
+        Ok(Some(array))
+    }
+}
+
+impl FromStr for VectorQuantization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "none" => Ok(VectorQuantization::None),
+            "int8" => Ok(VectorQuantization::Int8),
+            "binary" => Ok(VectorQuantization::Binary),
+            other => Err(anyhow::anyhow!(
+                "Unknown quantization '{}': expected 'none', 'int8' or 'binary'",
+                other
+            )),
+        }
+    }
+}
+
+/// Which vector columns a table stores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizationOptions {
+    pub quantization: VectorQuantization,
+    /// Keep the f32 vector column next to the quantized one, for rescoring
+    pub keep_full_precision: bool,
+}
+
+impl Default for QuantizationOptions {
+    fn default() -> Self {
+        Self {
+            quantization: VectorQuantization::None,
+            keep_full_precision: true,
+        }
+    }
+}
+
+impl QuantizationOptions {
+    /// Bytes of vector data stored per row
+    pub fn bytes_per_row(&self, dim: usize) -> usize {
+        let full_precision = if self.keep_full_precision {
+            dim * std::mem::size_of::<f32>()
+        } else {
+            0
+        };
+        full_precision + self.quantization.bytes_per_vector(dim)
+    }
+}
+
+/// Scale a vector so its largest absolute value maps to 127.
+/// The scale is not stored: the cosine distance between int8 vectors does not depend on it.
+pub fn quantize_int8(vector: &[f32]) -> Vec<i8> {
+    let max_abs = vector.iter().fold(0f32, |max, x| max.max(x.abs()));
+    if max_abs == 0.0 || !max_abs.is_finite() {
+        return vec![0; vector.len()];
+    }
+    let scale = 127.0 / max_abs;
+    vector
+        .iter()
+        .map(|x| (x * scale).round().clamp(-127.0, 127.0) as i8)
+        .collect()
+}
+
+/// Pack the signs of a vector, 8 dimensions per byte starting from the high bit
+pub fn quantize_binary(vector: &[f32]) -> Vec<u8> {
+    vector
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| **x > 0.0)
+                .fold(0u8, |byte, (i, _)| byte | (0x80 >> i))
+        })
+        .collect()
+}
+
+/// Cosine distance between two int8 vectors
+pub fn int8_cosine_distance(a: &[i8], b: &[i8]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0i64, 0i64, 0i64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (*x as i64, *y as i64);
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0 || norm_b == 0 {
+        return 1.0;
+    }
+    1.0 - dot as f32 / ((norm_a as f32).sqrt() * (norm_b as f32).sqrt())
+}
+
+/// Number of differing bits between two packed binary vectors
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Squared L2 distance, the metric Lance uses by default
+pub fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// A row found by a search, with its distance to the query
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub distance: f32,
+    pub row_id: u64,
+    pub text: String,
+}
+
+impl PartialEq for SearchHit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SearchHit {}
+
+impl PartialOrd for SearchHit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SearchHit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.row_id.cmp(&other.row_id))
+    }
+}
+
+/// Keeps the `capacity` closest candidates seen so far
+pub struct TopK {
+    capacity: usize,
+    heap: BinaryHeap<SearchHit>,
+}
+
+impl TopK {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            heap: BinaryHeap::with_capacity(capacity + 1),
+        }
+    }
+
+    /// Whether a candidate at this distance would be kept
+    pub fn accepts(&self, distance: f32) -> bool {
+        self.heap.len() < self.capacity
+            || self
+                .heap
+                .peek()
+                .is_some_and(|worst| distance < worst.distance)
+    }
+
+    pub fn push(&mut self, candidate: SearchHit) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.heap.push(candidate);
+        if self.heap.len() > self.capacity {
+            self.heap.pop();
+        }
+    }
+
+    /// The kept candidates, closest first
+    pub fn into_sorted_vec(self) -> Vec<SearchHit> {
+        self.heap.into_sorted_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantize() {
+        let vector = [0.5, -0.25, 0.0, 0.1, -1.0, 0.2, 0.3, -0.4, 0.9];
+        let int8 = quantize_int8(&vector);
+        assert_eq!(int8[..5], [64, -32, 0, 13, -127]);
+        // the cosine distance survives the quantization
+        assert!(int8_cosine_distance(&int8, &int8).abs() < 1e-6);
+
+        let binary = quantize_binary(&vector);
+        assert_eq!(binary, vec![0b1001_0110, 0b1000_0000]);
+        assert_eq!(VectorQuantization::Binary.bytes_per_vector(vector.len()), 2);
+        assert_eq!(hamming_distance(&binary, &quantize_binary(&[-0.5; 9])), 5);
+    }
+
+    #[test]
+    fn test_quantize_array() -> anyhow::Result<()> {
+        let vectors = vec![Some(vec![1.0, -1.0, 0.5]), None];
+        let array = VectorQuantization::Int8
+            .quantize_array(&vectors, 3)?
+            .expect("int8 column");
+        assert_eq!((array.len(), array.null_count()), (2, 1));
+        assert!(
+            VectorQuantization::None
+                .quantize_array(&vectors, 3)?
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_k() {
+        let mut top = TopK::new(2);
+        for (row_id, distance) in [3.0, 1.0, 2.0, 0.5].into_iter().enumerate() {
+            if top.accepts(distance) {
+                top.push(SearchHit {
+                    distance,
+                    row_id: row_id as u64,
+                    text: String::new(),
+                });
+            }
+        }
+        let rows: Vec<u64> = top.into_sorted_vec().iter().map(|c| c.row_id).collect();
+        assert_eq!(rows, vec![3, 1]);
+    }
+}
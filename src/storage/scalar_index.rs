@@ -0,0 +1,62 @@
+use lance_index::IndexType;
+use lance_index::scalar::{ScalarIndexParams, ScalarIndexType};
+use std::str::FromStr;
+
+/// The supported indexes on payload columns, used by filtered searches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScalarIndexKind {
+    /// Sorted values, for range and equality filters on high cardinality columns
+    #[default]
+    BTree,
+    /// One bitmap per value, for equality filters on low cardinality columns
+    Bitmap,
+    /// Bitmaps over the items of list columns, for `array_has_any` / `array_has_all` filters
+    LabelList,
+}
+
+impl ScalarIndexKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScalarIndexKind::BTree => "btree",
+            ScalarIndexKind::Bitmap => "bitmap",
+            ScalarIndexKind::LabelList => "label_list",
+        }
+    }
+
+    pub fn index_type(&self) -> IndexType {
+        match self {
+            ScalarIndexKind::BTree => IndexType::BTree,
+            ScalarIndexKind::Bitmap => IndexType::Bitmap,
+            ScalarIndexKind::LabelList => IndexType::LabelList,
+        }
+    }
+
+    pub fn params(&self) -> ScalarIndexParams {
+        ScalarIndexParams::new(match self {
+            ScalarIndexKind::BTree => ScalarIndexType::BTree,
+            ScalarIndexKind::Bitmap => ScalarIndexType::Bitmap,
+            ScalarIndexKind::LabelList => ScalarIndexType::LabelList,
+        })
+    }
+
+    /// Name of the index of a column, prefixed so that it cannot collide with the vector index
+    pub fn index_name(column: &str) -> String {
+        format!("scalar_{}_idx", column)
+    }
+}
+
+impl FromStr for ScalarIndexKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "btree" => Ok(ScalarIndexKind::BTree),
+            "bitmap" => Ok(ScalarIndexKind::Bitmap),
+            "label_list" => Ok(ScalarIndexKind::LabelList),
+            other => Err(anyhow::anyhow!(
+                "Unknown scalar index '{}': expected 'btree', 'bitmap' or 'label_list'",
+                other
+            )),
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use lance::index::vector::VectorIndexParams;
+use lance_index::vector::hnsw::builder::HnswBuildParams;
+use lance_index::vector::ivf::IvfBuildParams;
+use lance_index::vector::sq::builder::SQBuildParams;
+use lance_linalg::distance::DistanceType;
+use std::str::FromStr;
+
+use crate::storage::quantization::l2_distance;
+
+/// Name of the ANN index built on the vector column
+pub const VECTOR_INDEX_NAME: &str = "vector_idx";
+
+/// Iterations of the k-means training of the IVF partitions and PQ codebooks
+const MAX_TRAINING_ITERATIONS: usize = 50;
+
+/// Rows needed to train the 256 centroids of each 8-bit PQ codebook
+const PQ_MIN_ROWS: usize = 256;
+
+/// Distance between a query and the stored vectors, a property of the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// Squared euclidean distance, Lance's default
+    #[default]
+    L2,
+    /// One minus the cosine similarity
+    Cosine,
+    /// One minus the dot product, for vectors whose norm carries meaning
+    Dot,
+}
+
+impl DistanceMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceMetric::L2 => "l2",
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::Dot => "dot",
+        }
+    }
+
+    pub fn distance_type(&self) -> DistanceType {
+        match self {
+            DistanceMetric::L2 => DistanceType::L2,
+            DistanceMetric::Cosine => DistanceType::Cosine,
+            DistanceMetric::Dot => DistanceType::Dot,
+        }
+    }
+
+    /// Distance between two vectors, as Lance computes it
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        let dot = || a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        match self {
+            DistanceMetric::L2 => l2_distance(a, b),
+            DistanceMetric::Cosine => {
+                let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+                let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    return 1.0;
+                }
+                1.0 - dot() / (norm_a * norm_b)
+            }
+            DistanceMetric::Dot => 1.0 - dot(),
+        }
+    }
+}
+
+impl FromStr for DistanceMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "l2" => Ok(DistanceMetric::L2),
+            "cosine" => Ok(DistanceMetric::Cosine),
+            "dot" => Ok(DistanceMetric::Dot),
+            other => Err(anyhow::anyhow!(
+                "Unknown distance metric '{}': expected 'l2', 'cosine' or 'dot'",
+                other
+            )),
+        }
+    }
+}
+
+/// The supported ANN index layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VectorIndexType {
+    /// Inverted file partitions with product quantized vectors: small, fast to build
+    #[default]
+    IvfPq,
+    /// Inverted file partitions, each searched with an HNSW graph over scalar quantized vectors:
+    /// higher recall at a larger size
+    IvfHnswSq,
+}
+
+impl VectorIndexType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VectorIndexType::IvfPq => "IVF_PQ",
+            VectorIndexType::IvfHnswSq => "IVF_HNSW_SQ",
+        }
+    }
+}
+
+impl FromStr for VectorIndexType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "IVF_PQ" => Ok(VectorIndexType::IvfPq),
+            "IVF_HNSW_SQ" => Ok(VectorIndexType::IvfHnswSq),
+            _ => Err(anyhow::anyhow!(
+                "Unknown vector index type '{}': expected 'IVF_PQ' or 'IVF_HNSW_SQ'",
+                s
+            )),
+        }
+    }
+}
+
+/// Parameters of the ANN index built on the vector column
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorIndexOptions {
+    pub index_type: VectorIndexType,
+    /// Metric the index is built for; defaults to the metric of the table
+    pub metric: Option<DistanceMetric>,
+    /// IVF partitions; defaults to the square root of the row count
+    pub num_partitions: Option<usize>,
+    /// PQ sub-vectors; defaults to dim / 16, or dim / 8, so that it divides the dimension
+    pub num_sub_vectors: Option<usize>,
+    /// HNSW neighbours per node
+    pub num_edges: usize,
+    /// HNSW candidate list size while building the graph
+    pub ef_construction: usize,
+}
+
+impl Default for VectorIndexOptions {
+    fn default() -> Self {
+        Self {
+            index_type: VectorIndexType::default(),
+            metric: None,
+            num_partitions: None,
+            num_sub_vectors: None,
+            num_edges: 20,
+            ef_construction: 150,
+        }
+    }
+}
+
+impl VectorIndexOptions {
+    /// Lance build parameters for a table of `num_rows` vectors of `dim` dimensions, compared
+    /// with `metric`
+    pub fn to_params(
+        &self,
+        num_rows: usize,
+        dim: usize,
+        metric: DistanceMetric,
+    ) -> Result<VectorIndexParams> {
+        let num_partitions = self
+            .num_partitions
+            .unwrap_or_else(|| ((num_rows as f64).sqrt() as usize).max(1));
+        if num_partitions == 0 || num_partitions > num_rows {
+            return Err(anyhow::anyhow!(
+                "num_partitions must be between 1 and the {} rows of the table, got {}",
+                num_rows,
+                num_partitions
+            ));
+        }
+        let distance_type = metric.distance_type();
+        match self.index_type {
+            VectorIndexType::IvfPq => {
+                if num_rows < PQ_MIN_ROWS {
+                    return Err(anyhow::anyhow!(
+                        "IVF_PQ needs at least {} rows to train its codebooks, the table has {}",
+                        PQ_MIN_ROWS,
+                        num_rows
+                    ));
+                }
+                let num_sub_vectors = self.num_sub_vectors.unwrap_or(match dim {
+                    dim if dim % 16 == 0 => dim / 16,
+                    dim if dim % 8 == 0 => dim / 8,
+                    _ => 1,
+                });
+                if num_sub_vectors == 0 || dim % num_sub_vectors != 0 {
+                    return Err(anyhow::anyhow!(
+                        "num_sub_vectors must divide the vector dimension {}, got {}",
+                        dim,
+                        num_sub_vectors
+                    ));
+                }
+                Ok(VectorIndexParams::ivf_pq(
+                    num_partitions,
+                    8,
+                    num_sub_vectors,
+                    distance_type,
+                    MAX_TRAINING_ITERATIONS,
+                ))
+            }
+            VectorIndexType::IvfHnswSq => {
+                let hnsw = HnswBuildParams::default()
+                    .num_edges(self.num_edges)
+                    .ef_construction(self.ef_construction);
+                Ok(VectorIndexParams::with_ivf_hnsw_sq_params(
+                    distance_type,
+                    IvfBuildParams::new(num_partitions),
+                    hnsw,
+                    SQBuildParams::default(),
+                ))
+            }
+        }
+    }
+}
+
+/// What is known about the vector index of a table, so that searches use the metric it was
+/// built with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorIndexInfo {
+    pub index_type: VectorIndexType,
+    pub metric: DistanceMetric,
+}
+
+impl VectorIndexInfo {
+    /// Reads the index type and metric from the statistics Lance reports for the index
+    pub fn from_statistics(statistics: &str) -> Result<Self> {
+        let statistics: serde_json::Value = serde_json::from_str(statistics)?;
+        let index_type = statistics
+            .get("index_type")
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Vector index statistics have no index_type"))?;
+        // every delta of the index is built with the same metric
+        let metric = statistics
+            .get("indices")
+            .and_then(|indices| indices.get(0))
+            .and_then(|index| index.get("metric_type"))
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Vector index statistics have no metric_type"))?;
+        let index_type = match index_type.to_ascii_uppercase().as_str() {
+            // IVF_PQ indices report their partitioning only
+            "IVF" => VectorIndexType::IvfPq,
+            other => other.parse()?,
+        };
+        Ok(Self {
+            index_type,
+            metric: metric.to_ascii_lowercase().parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_index_options() {
+        let options = VectorIndexOptions::default();
+        assert!(options.to_params(10_000, 1024, DistanceMetric::L2).is_ok());
+        // 100 does not split into 16 or 8 sub-vectors
+        let options = VectorIndexOptions {
+            num_sub_vectors: Some(16),
+            ..Default::default()
+        };
+        assert!(options.to_params(10_000, 100, DistanceMetric::L2).is_err());
+        let options = VectorIndexOptions {
+            num_partitions: Some(64),
+            ..Default::default()
+        };
+        assert!(options.to_params(10, 1024, DistanceMetric::L2).is_err());
+        // too few rows to train the PQ codebooks, but enough for HNSW
+        assert!(
+            VectorIndexOptions::default()
+                .to_params(100, 1024, DistanceMetric::Cosine)
+                .is_err()
+        );
+        let options = VectorIndexOptions {
+            index_type: VectorIndexType::IvfHnswSq,
+            ..Default::default()
+        };
+        assert!(options.to_params(100, 1024, DistanceMetric::Cosine).is_ok());
+        assert_eq!(
+            "ivf_hnsw_sq".parse::<VectorIndexType>().unwrap(),
+            VectorIndexType::IvfHnswSq
+        );
+    }
+
+    #[test]
+    fn test_distance_metric() {
+        let (a, b) = ([3.0, 4.0], [6.0, 8.0]);
+        assert_eq!(DistanceMetric::L2.distance(&a, &b), 25.0);
+        assert!(DistanceMetric::Cosine.distance(&a, &b).abs() < 1e-6);
+        assert_eq!(DistanceMetric::Dot.distance(&a, &b), -49.0);
+        assert_eq!(DistanceMetric::Cosine.distance(&a, &[0.0, 0.0]), 1.0);
+        assert!("hamming".parse::<DistanceMetric>().is_err());
+    }
+
+    #[test]
+    fn test_vector_index_info_from_statistics() -> Result<()> {
+        let statistics = r#"{"index_type": "IVF_HNSW_SQ", "name": "vector_idx",
+            "indices": [{"index_type": "IVF_HNSW_SQ", "metric_type": "cosine"}]}"#;
+        assert_eq!(
+            VectorIndexInfo::from_statistics(statistics)?,
+            VectorIndexInfo {
+                index_type: VectorIndexType::IvfHnswSq,
+                metric: DistanceMetric::Cosine,
+            }
+        );
+        let statistics = r#"{"index_type": "IVF", "indices": [{"metric_type": "l2"}]}"#;
+        assert_eq!(
+            VectorIndexInfo::from_statistics(statistics)?.index_type,
+            VectorIndexType::IvfPq
+        );
+        assert!(VectorIndexInfo::from_statistics(r#"{"index_type": "IVF_PQ"}"#).is_err());
+        Ok(())
+    }
+}